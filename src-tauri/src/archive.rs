@@ -1,164 +1,606 @@
+use std::cell::Cell;
 use std::fs::File;
-use std::io;
-use std::path::Path;
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
 
 use flate2::read::GzDecoder;
 use tar::Archive;
-use tauri::{AppHandle, Manager};
+use tauri::{AppHandle, Runtime};
 use zip::ZipArchive;
 
-use crate::net::DownloadInfo;
-
-pub fn extract_tar_gz(app: AppHandle, name: &str, output_dir: &String, archive: &mut Archive<GzDecoder<File>>) -> Result<(), String> {
-// Emit event
-    app.emit_all(
-        "downloadProgress",
-        DownloadInfo {
-            downloaded: 0,
-            total: 0,
-            downloading: true,
-            status: format!("Extracting: {}", name),
-            percent: 100,
-        },
-    )
-        .map_err(|e| format!("Failed to emit extract event: {:?}", e))?;
+use crate::net::{DownloadPhase, ProgressEmitter};
+use crate::util::Error;
+
+/// File type bits of a unix mode, and the value marking a symlink.
+const S_IFMT: u32 = 0o170000;
+const S_IFLNK: u32 = 0o120000;
+
+fn unsafe_entry(entry: &str, reason: &str) -> Error {
+    Error::UnsafeArchiveEntry {
+        entry: entry.to_string(),
+        reason: reason.to_string(),
+    }
+}
+
+/// Whether a path starts at the root or at a drive like `C:`, on any platform.
+fn is_absolute(normalized: &str) -> bool {
+    let bytes = normalized.as_bytes();
+    normalized.starts_with('/') || (bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':')
+}
+
+/// Joins an archive entry name onto `root`, rejecting absolute paths and `..` components.
+///
+/// Backslashes and drive letters are handled on every platform, so Windows style names can't
+/// sneak past the checks elsewhere.
+pub fn safe_join(root: &Path, entry: &str) -> Result<PathBuf, Error> {
+    if entry.contains('\0') {
+        return Err(unsafe_entry(entry, "contains a NUL byte"));
+    }
+
+    let normalized = entry.replace('\\', "/");
+    if is_absolute(&normalized) {
+        return Err(unsafe_entry(entry, "absolute path"));
+    }
+
+    let mut path = root.to_path_buf();
+    for component in Path::new(&normalized).components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::CurDir => {}
+            Component::ParentDir => return Err(unsafe_entry(entry, "contains a '..' component")),
+            Component::RootDir | Component::Prefix(_) => return Err(unsafe_entry(entry, "absolute path")),
+        }
+    }
+    Ok(path)
+}
+
+/// Makes sure no symlink on the way to `path` leads outside of `root`, and that `path` itself is
+/// not a symlink that writing would follow.
+pub fn check_inside(root: &Path, path: &Path, entry: &str) -> Result<(), Error> {
+    if std::fs::symlink_metadata(path)
+        .map(|it| it.file_type().is_symlink())
+        .unwrap_or(false)
+    {
+        return Err(unsafe_entry(entry, "would overwrite a symlink"));
+    }
+
+    let root = root.canonicalize()?;
+    let mut existing = Some(path);
+    while let Some(dir) = existing {
+        if dir.exists() {
+            if !dir.canonicalize()?.starts_with(&root) {
+                return Err(unsafe_entry(entry, "escapes the output directory through a symlink"));
+            }
+            return Ok(());
+        }
+        existing = dir.parent();
+    }
+    Err(unsafe_entry(entry, "escapes the output directory"))
+}
+
+/// Checks that a symlink at `link` pointing to `target` resolves to a path inside `root`.
+///
+/// The check is lexical, the target doesn't have to exist yet.
+pub fn check_symlink_target(root: &Path, link: &Path, target: &str, entry: &str) -> Result<(), Error> {
+    let normalized = target.replace('\\', "/");
+    if is_absolute(&normalized) {
+        return Err(unsafe_entry(entry, "symlink to an absolute path"));
+    }
+
+    let parent = link
+        .parent()
+        .and_then(|it| it.strip_prefix(root).ok())
+        .ok_or_else(|| unsafe_entry(entry, "symlink outside of the output directory"))?;
+    let mut depth = parent.components().count();
+    for component in Path::new(&normalized).components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if depth == 0 {
+                    return Err(unsafe_entry(entry, "symlink escapes the output directory"));
+                }
+                depth -= 1;
+            }
+            Component::RootDir | Component::Prefix(_) => {
+                return Err(unsafe_entry(entry, "symlink to an absolute path"))
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn create_symlink(root: &Path, link: &Path, target: &str, entry: &str) -> Result<(), Error> {
+    check_symlink_target(root, link, target, entry)?;
+    std::os::unix::fs::symlink(target, link)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn create_symlink(root: &Path, link: &Path, target: &str, entry: &str) -> Result<(), Error> {
+    // Creating symlinks needs extra privileges on Windows, copy the target instead if it's there.
+    check_symlink_target(root, link, target, entry)?;
+    let source = link.parent().unwrap_or(root).join(target);
+    if source.is_file() {
+        std::fs::copy(source, link)?;
+    } else {
+        println!("Skipping symlink '{}', target doesn't exist yet", entry);
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode & 0o7777))
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> io::Result<()> {
+    Ok(())
+}
+
+/// Resolves the destination of an archive entry and creates its parent directories.
+pub fn prepare_entry(root: &Path, entry: &str) -> Result<PathBuf, Error> {
+    let dest_path = safe_join(root, entry)?;
+    check_inside(root, &dest_path, entry)?;
+    if let Some(parent) = dest_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    // Creating the parents can't have escaped, but a race with another process could have.
+    check_inside(root, &dest_path, entry)?;
+    Ok(dest_path)
+}
+
+/// Extracts an SDK package into a directory.
+pub trait Extractor {
+    /// Name of the archive format, used in logs and errors.
+    fn format(&self) -> &'static str;
+
+    fn extract(&self, app: AppHandle, name: &str, archive_path: &Path, output_dir: &str) -> Result<(), Error>;
+}
+
+pub struct TarGzExtractor;
+pub struct TarXzExtractor;
+pub struct TarZstExtractor;
+pub struct ZipExtractor;
+
+fn open_archive(archive_path: &Path) -> Result<File, Error> {
+    File::open(archive_path).map_err(|e| Error::Launch(format!("Failed to open SDK package: {:?}", e)))
+}
+
+type CountedArchive = (CountingReader<File>, Rc<Cell<u64>>, u64);
+
+/// Counts the compressed bytes read from a package, streamed archives have no other way to tell
+/// how far along extraction is.
+struct CountingReader<R: Read> {
+    inner: R,
+    consumed: Rc<Cell<u64>>,
+}
+
+impl CountingReader<File> {
+    /// Opens the package, returning the reader, its byte counter and the package size.
+    fn open(archive_path: &Path) -> Result<CountedArchive, Error> {
+        let file = open_archive(archive_path)?;
+        let total = file
+            .metadata()
+            .map_err(|e| Error::Launch(format!("Failed to open SDK package: {:?}", e)))?
+            .len();
+        let consumed = Rc::new(Cell::new(0));
+        let reader = CountingReader {
+            inner: file,
+            consumed: consumed.clone(),
+        };
+        Ok((reader, consumed, total))
+    }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.consumed.set(self.consumed.get() + read as u64);
+        Ok(read)
+    }
+}
+
+impl Extractor for TarGzExtractor {
+    fn format(&self) -> &'static str {
+        "tar.gz"
+    }
+
+    fn extract(&self, app: AppHandle, name: &str, archive_path: &Path, output_dir: &str) -> Result<(), Error> {
+        let (reader, consumed, total) = CountingReader::open(archive_path)?;
+        let decompressed = GzDecoder::new(reader);
+        extract_tar(app, name, output_dir, &mut Archive::new(decompressed), &consumed, total)
+    }
+}
+
+impl Extractor for TarXzExtractor {
+    fn format(&self) -> &'static str {
+        "tar.xz"
+    }
+
+    fn extract(&self, app: AppHandle, name: &str, archive_path: &Path, output_dir: &str) -> Result<(), Error> {
+        let (reader, consumed, total) = CountingReader::open(archive_path)?;
+        let decompressed = xz2::read::XzDecoder::new(reader);
+        extract_tar(app, name, output_dir, &mut Archive::new(decompressed), &consumed, total)
+    }
+}
+
+impl Extractor for TarZstExtractor {
+    fn format(&self) -> &'static str {
+        "tar.zst"
+    }
+
+    fn extract(&self, app: AppHandle, name: &str, archive_path: &Path, output_dir: &str) -> Result<(), Error> {
+        let (reader, consumed, total) = CountingReader::open(archive_path)?;
+        let decompressed = zstd::stream::read::Decoder::new(reader)
+            .map_err(|e| Error::Launch(format!("Failed to open SDK package: {:?}", e)))?;
+        extract_tar(app, name, output_dir, &mut Archive::new(decompressed), &consumed, total)
+    }
+}
+
+impl Extractor for ZipExtractor {
+    fn format(&self) -> &'static str {
+        "zip"
+    }
+
+    fn extract(&self, app: AppHandle, name: &str, archive_path: &Path, output_dir: &str) -> Result<(), Error> {
+        let archive = &mut ZipArchive::new(open_archive(archive_path)?)
+            .map_err(|e| Error::Launch(format!("Failed to open SDK package: {:?}", e)))?;
+        extract_zip(app, name, output_dir, archive)
+    }
+}
+
+/// Archive format names paired with their file extensions and magic bytes.
+const FORMATS: [(&str, &[&str], &[u8]); 4] = [
+    ("tar.gz", &[".tar.gz", ".tgz"], &[0x1f, 0x8b]),
+    ("tar.xz", &[".tar.xz", ".txz"], &[0xfd, b'7', b'z', b'X', b'Z', 0x00]),
+    ("tar.zst", &[".tar.zst", ".tzst"], &[0x28, 0xb5, 0x2f, 0xfd]),
+    ("zip", &[".zip"], &[b'P', b'K', 0x03, 0x04]),
+];
+
+fn extractor_by_format(format: &str) -> Option<Box<dyn Extractor>> {
+    match format {
+        "tar.gz" => Some(Box::new(TarGzExtractor)),
+        "tar.xz" => Some(Box::new(TarXzExtractor)),
+        "tar.zst" => Some(Box::new(TarZstExtractor)),
+        "zip" => Some(Box::new(ZipExtractor)),
+        _ => None,
+    }
+}
+
+/// Picks the extractor for an archive by its magic bytes, falling back to its extension.
+pub fn extractor_for(archive_path: &Path) -> Result<Box<dyn Extractor>, Error> {
+    let file_name = archive_path
+        .file_name()
+        .map(|it| it.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    let mut magic = Vec::with_capacity(8);
+    File::open(archive_path)?.take(8).read_to_end(&mut magic)?;
+
+    let by_extension = FORMATS
+        .iter()
+        .find(|(_, extensions, _)| extensions.iter().any(|it| file_name.ends_with(it)))
+        .map(|it| it.0);
+    let by_magic = FORMATS
+        .iter()
+        .find(|(_, _, bytes)| magic.starts_with(bytes))
+        .map(|it| it.0);
+
+    let format = match (by_extension, by_magic) {
+        (Some(extension), Some(magic)) if extension != magic => {
+            println!("SDK package {} looks like {} despite its name, using that", file_name, magic);
+            magic
+        }
+        (_, Some(magic)) => magic,
+        (Some(extension), None) => extension,
+        (None, None) => return Err(Error::UnsupportedArchive(file_name)),
+    };
+    extractor_by_format(format).ok_or(Error::UnsupportedArchive(file_name))
+}
+
+/// Extracts a tar archive, reporting progress as the compressed bytes `consumed` out of `total`.
+pub fn extract_tar<R: Runtime, A: Read>(
+    app: AppHandle<R>,
+    name: &str,
+    output_dir: &str,
+    archive: &mut Archive<A>,
+    consumed: &Cell<u64>,
+    total: u64,
+) -> Result<(), Error> {
+    let mut progress = ProgressEmitter::new(&app, DownloadPhase::Extracting, format!("Extracting: {}", name));
+    progress.emit(0, total, true);
 
     std::fs::create_dir_all(output_dir)
-        .map_err(|e| format!("Failed to create output directory: {:?}", e))?;
+        .map_err(|e| Error::Launch(format!("Failed to create output directory: {:?}", e)))?;
 
     let entries = &mut archive
         .entries()
-        .map_err(|e| format!("Failed to list SDK package entries: {:?}", e))?;
-    let mut buf: Vec<u8> = vec![];
+        .map_err(|e| Error::Launch(format!("Failed to list SDK package entries: {:?}", e)))?;
 
-    for (extracted, entry) in entries.enumerate() {
-        let out_dir = output_dir.clone();
-        let mut entry = entry.map_err(|e| format!("Failed to get entry: {:?}", e))?;
+    for entry in entries {
+        let out_dir = output_dir.to_string();
+        let mut entry = entry.map_err(|e| Error::Launch(format!("Failed to get entry: {:?}", e)))?;
         let path = entry
             .path()
-            .map_err(|e| format!("Failed to get entry: {:?}", e))?;
+            .map_err(|e| Error::Launch(format!("Failed to get entry: {:?}", e)))?;
 
         println!("Extracting: {}/{}", name, path.to_string_lossy());
 
-        // Emit event
-        app.emit_all(
-            "downloadProgress",
-            DownloadInfo {
-                downloaded: extracted as u64,
-                total: extracted as u64,
-                downloading: true,
-                status: format!("Extracting: {:?}", path),
-                percent: (100),
-            },
-        )
-            .map_err(|e| format!("Failed to emit extract event: {:?}", e))?;
-
-        let target_path = format!("{}/{}", out_dir, path.to_string_lossy());
-
-        // Create parent directories if they don't exist
-        if let Some(parent) = path.parent() {
-            std::fs::create_dir_all(format!("{}/{}", out_dir, parent.to_string_lossy()))
-                .map_err(|e| format!("Failed to output directories: {:?}", e))?;
+        let entry_name = path.to_string_lossy().to_string();
+        let target_path = prepare_entry(Path::new(&out_dir), &entry_name)?;
+
+        if entry.header().entry_type().is_symlink() {
+            let target = entry
+                .link_name()
+                .map_err(|e| Error::Launch(format!("Failed to get entry: {:?}", e)))?
+                .map(|it| it.to_string_lossy().to_string())
+                .unwrap_or_default();
+            check_symlink_target(Path::new(&out_dir), &target_path, &target, &entry_name)?;
         }
 
         // If the entry is a directory, create it
         if entry.header().entry_type().is_dir() {
             std::fs::create_dir_all(&target_path)
-                .map_err(|e| format!("Failed to create directory: {:?}", e))?;
+                .map_err(|e| Error::Launch(format!("Failed to create directory: {:?}", e)))?;
         } else {
             entry
                 .unpack_in(out_dir)
-                .map_err(|e| format!("Failed to unpack file: {:?}", e))?;
-            buf.clear();
+                .map_err(|e| Error::Launch(format!("Failed to unpack file: {:?}", e)))?;
         }
+
+        progress.progress(consumed.get(), total);
     }
 
-    // Emit event
-    app.emit_all(
-        "downloadProgress",
-        DownloadInfo {
-            downloaded: 1,
-            total: 1,
-            downloading: false,
-            status: "Completed!".to_string(),
-            percent: 100,
-        },
-    )
-        .map_err(|e| format!("Failed to emit extract event: {:?}", e))?;
+    progress.emit(total, total, true);
     Ok(())
 }
 
-pub fn extract_zip(app: AppHandle, name: &str, output_dir: &String, archive: &mut ZipArchive<File>) -> Result<(), String> {
-// Emit event
-    app.emit_all(
-        "downloadProgress",
-        DownloadInfo {
-            downloaded: 0,
-            total: 0,
-            downloading: true,
-            status: format!("Extracting: {}", name),
-            percent: 100,
-        },
-    )
-        .map_err(|e| format!("Failed to emit extract event: {:?}", e))?;
+/// Extracts a zip archive, reporting progress as the uncompressed bytes written.
+pub fn extract_zip<R: Runtime>(app: AppHandle<R>, name: &str, output_dir: &str, archive: &mut ZipArchive<File>) -> Result<(), Error> {
+    let len = archive.len();
 
-    // Iterate over each file in the zip archive
+    // The central directory knows every entry's size up front
+    let mut total = 0;
+    for i in 0..len {
+        total += archive.by_index_raw(i).map(|it| it.size()).unwrap_or(0);
+    }
+    let mut written = 0;
 
-    let len = archive.len();
+    let mut progress = ProgressEmitter::new(&app, DownloadPhase::Extracting, format!("Extracting: {}", name));
+    progress.emit(0, total, true);
 
-    for (extracted, i) in (0..len).enumerate() {
-        let mut file = archive.by_index(i).map_err(|e| format!("Failed to get zip file: {:?}", e))?;
-
-        // Emit event
-        app.emit_all(
-            "downloadProgress",
-            DownloadInfo {
-                downloaded: extracted as u64,
-                total: len as u64,
-                downloading: true,
-                status: format!("Extracting: {:?}", file.name()),
-                percent: (100),
-            },
-        )
-            .map_err(|e| format!("Failed to emit extract event: {:?}", e))?;
+    std::fs::create_dir_all(output_dir)
+        .map_err(|e| Error::Launch(format!("Failed to create output directory: {:?}", e)))?;
+
+    // Iterate over each file in the zip archive
+    for i in 0..len {
+        let mut file = archive.by_index(i).map_err(|e| Error::Launch(format!("Failed to get zip file: {:?}", e)))?;
 
         // Get the file's name
         let file_name = &file.name().to_string();
 
-        let dest_path = Path::new(output_dir).join(file_name);
-
-        // Create directories if needed
-        if let Some(parent) = dest_path.parent() {
-            if !parent.exists() {
-                std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create entry destination directory: {:?}", e))?;
-            }
-        }
+        let dest_path = prepare_entry(Path::new(output_dir), file_name)?;
 
+        let mode = file.unix_mode();
         if file.is_dir() {
-            std::fs::create_dir_all(&dest_path).map_err(|e| format!("Failed to create entry destination directory: {:?}", e))?;
+            std::fs::create_dir_all(&dest_path).map_err(|e| Error::Launch(format!("Failed to create entry destination directory: {:?}", e)))?;
             println!("Extracting '{file_name}' -> '{:?}'", &dest_path.to_string_lossy());
+        } else if mode.map(|it| it & S_IFMT == S_IFLNK).unwrap_or(false) {
+            // Symlinks store their target as the entry's content
+            let mut target = String::new();
+            io::Read::read_to_string(&mut file, &mut target).map_err(|e| Error::Launch(format!("Failed to read symlink entry: {:?}", e)))?;
+            create_symlink(Path::new(output_dir), &dest_path, &target, file_name)?;
+            println!("Linking '{file_name}' -> '{target}'");
         } else {
             // Create the file
-            let mut dest_file = File::create(&dest_path).map_err(|e| format!("Failed to create destination file: {:?}", e))?;
+            let mut dest_file = File::create(&dest_path).map_err(|e| Error::Launch(format!("Failed to create destination file: {:?}", e)))?;
 
             // Copy the contents of the file from the zip archive to the destination file
-            io::copy(&mut file, &mut dest_file).map_err(|e| format!("Failed to extract zip entry: {:?}", e))?;
+            io::copy(&mut file, &mut dest_file).map_err(|e| Error::Launch(format!("Failed to extract zip entry: {:?}", e)))?;
+            if let Some(mode) = mode {
+                set_mode(&dest_path, mode).map_err(|e| Error::Launch(format!("Failed to set permissions of zip entry: {:?}", e)))?;
+            }
             let dest_name = dest_path.to_string_lossy();
             println!("Extracting '{file_name}' -> '{dest_name}'");
         }
+
+        written += file.size();
+        progress.progress(written, total);
     }
 
-    // Emit event
-    app.emit_all(
-        "downloadProgress",
-        DownloadInfo {
-            downloaded: 1,
-            total: 1,
-            downloading: false,
-            status: "Completed!".to_string(),
-            percent: 100,
-        },
-    )
-        .map_err(|e| format!("Failed to emit extract event: {:?}", e))?;
+    progress.emit(total, total, true);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Debug;
+    use std::io::{Cursor, Write};
+
+    use zip::write::FileOptions;
+    use zip::ZipWriter;
+
+    use super::*;
+    use crate::util;
+
+    fn assert_unsafe<T: Debug>(result: Result<T, Error>, expected: &str) {
+        match result {
+            Err(Error::UnsafeArchiveEntry { entry, .. }) => assert_eq!(entry, expected),
+            other => panic!("Expected '{}' to be rejected, got {:?}", expected, other),
+        }
+    }
+
+    #[test]
+    fn safe_join_accepts_relative_names() {
+        let root = Path::new("/out");
+        assert_eq!(safe_join(root, "bin/java").unwrap(), root.join("bin").join("java"));
+        assert_eq!(safe_join(root, "./bin/./java").unwrap(), root.join("bin").join("java"));
+        assert_eq!(safe_join(root, "bin\\java").unwrap(), root.join("bin").join("java"));
+    }
+
+    #[test]
+    fn safe_join_rejects_escaping_names() {
+        let root = Path::new("/out");
+        for entry in [
+            "../evil",
+            "bin/../../evil",
+            "..\\evil",
+            "bin\\..\\..\\evil",
+            "/etc/passwd",
+            "\\evil",
+            "C:\\Windows\\evil",
+            "c:/evil",
+            "bin/java\0.txt",
+        ] {
+            assert_unsafe(safe_join(root, entry), entry);
+        }
+    }
+
+    #[test]
+    fn symlink_targets_must_stay_inside() {
+        let root = Path::new("/out");
+        let link = root.join("lib").join("link");
+        check_symlink_target(root, &link, "libjava.so", "lib/link").unwrap();
+        check_symlink_target(root, &link, "../bin/java", "lib/link").unwrap();
+        for target in ["../../etc/passwd", "../bin/../../evil", "/etc/passwd", "C:\\evil", "..\\..\\evil"] {
+            assert_unsafe(check_symlink_target(root, &link, target, "lib/link"), "lib/link");
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn prepare_entry_refuses_to_write_through_symlinks() {
+        let dir = util::test_dir("archive-symlink");
+        let root = dir.join("out");
+        let outside = dir.join("outside");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        std::os::unix::fs::symlink(&outside, root.join("link")).unwrap();
+
+        assert_unsafe(prepare_entry(&root, "link/evil"), "link/evil");
+        assert_unsafe(prepare_entry(&root, "link/sub/evil"), "link/sub/evil");
+        assert_unsafe(prepare_entry(&root, "link"), "link");
+        assert!(!outside.join("sub").exists());
+
+        assert_eq!(prepare_entry(&root, "bin/java").unwrap(), root.join("bin").join("java"));
+        assert!(root.join("bin").is_dir());
+    }
+
+    fn write_zip(path: &Path, build: impl FnOnce(&mut ZipWriter<File>)) -> ZipArchive<File> {
+        let mut zip = ZipWriter::new(File::create(path).unwrap());
+        build(&mut zip);
+        zip.finish().unwrap();
+        ZipArchive::new(File::open(path).unwrap()).unwrap()
+    }
+
+    fn add_file(zip: &mut ZipWriter<File>, name: &str) {
+        zip.start_file(name, FileOptions::default()).unwrap();
+        zip.write_all(b"data").unwrap();
+    }
+
+    #[test]
+    fn extract_zip_rejects_parent_entries() {
+        let dir = util::test_dir("archive-zip");
+        let out = dir.join("out");
+        let mut archive = write_zip(&dir.join("sdk.zip"), |zip| {
+            add_file(zip, "bin/java");
+            add_file(zip, "../evil");
+        });
+
+        let app = tauri::test::mock_app();
+        let result = extract_zip(app.handle(), "sdk.zip", out.to_str().unwrap(), &mut archive);
+
+        assert_unsafe(result, "../evil");
+        assert!(out.join("bin/java").is_file());
+        assert!(!dir.join("evil").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn extract_zip_rejects_escaping_symlinks() {
+        let dir = util::test_dir("archive-zip-symlink");
+        let out = dir.join("out");
+        let mut archive = write_zip(&dir.join("sdk.zip"), |zip| {
+            zip.add_symlink("lib/link", "../../outside", FileOptions::default()).unwrap();
+        });
+
+        let app = tauri::test::mock_app();
+        let result = extract_zip(app.handle(), "sdk.zip", out.to_str().unwrap(), &mut archive);
+
+        assert_unsafe(result, "lib/link");
+        assert!(std::fs::symlink_metadata(out.join("lib/link")).is_err());
+    }
+
+    /// Tar entry with a raw name, `tar::Builder` refuses to write unsafe names itself.
+    fn tar_entry(builder: &mut tar::Builder<Vec<u8>>, name: &str, entry_type: tar::EntryType, link: Option<&str>) {
+        let mut header = tar::Header::new_gnu();
+        header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+        header.set_entry_type(entry_type);
+        header.set_mode(0o644);
+        if let Some(link) = link {
+            header.set_link_name(link).unwrap();
+        }
+        let data: &[u8] = if entry_type.is_file() { b"data" } else { b"" };
+        header.set_size(data.len() as u64);
+        header.set_cksum();
+        builder.append(&header, data).unwrap();
+    }
+
+    fn extract_tar_bytes(out: &Path, build: impl FnOnce(&mut tar::Builder<Vec<u8>>)) -> Result<(), Error> {
+        let mut builder = tar::Builder::new(vec![]);
+        build(&mut builder);
+        let bytes = builder.into_inner().unwrap();
+        let total = bytes.len() as u64;
+        let app = tauri::test::mock_app();
+        let mut archive = Archive::new(Cursor::new(bytes));
+        extract_tar(app.handle(), "sdk.tar", out.to_str().unwrap(), &mut archive, &Cell::new(0), total)
+    }
+
+    #[test]
+    fn extract_tar_rejects_parent_entries() {
+        let dir = util::test_dir("archive-tar");
+        let out = dir.join("out");
+        let result = extract_tar_bytes(&out, |builder| {
+            tar_entry(builder, "bin/java", tar::EntryType::Regular, None);
+            tar_entry(builder, "../evil", tar::EntryType::Regular, None);
+        });
+
+        assert_unsafe(result, "../evil");
+        assert!(out.join("bin/java").is_file());
+        assert!(!dir.join("evil").exists());
+    }
+
+    #[test]
+    fn extract_tar_rejects_escaping_symlinks() {
+        let dir = util::test_dir("archive-tar-symlink");
+        let out = dir.join("out");
+        let result = extract_tar_bytes(&out, |builder| {
+            tar_entry(builder, "lib/link", tar::EntryType::Symlink, Some("../../outside"));
+        });
+
+        assert_unsafe(result, "lib/link");
+        assert!(std::fs::symlink_metadata(out.join("lib/link")).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn extract_tar_rejects_writing_through_a_symlink() {
+        let dir = util::test_dir("archive-tar-through");
+        let out = dir.join("out");
+        std::fs::create_dir_all(dir.join("outside")).unwrap();
+        std::fs::create_dir_all(&out).unwrap();
+        std::os::unix::fs::symlink(dir.join("outside"), out.join("lib")).unwrap();
+
+        let result = extract_tar_bytes(&out, |builder| {
+            tar_entry(builder, "lib/evil", tar::EntryType::Regular, None);
+        });
+
+        assert_unsafe(result, "lib/evil");
+        assert!(!dir.join("outside/evil").exists());
+    }
+}
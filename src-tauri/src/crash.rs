@@ -0,0 +1,360 @@
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+use std::time::UNIX_EPOCH;
+
+use serde::{Deserialize, Serialize};
+use zip::write::FileOptions;
+use zip::{ZipArchive, ZipWriter};
+
+use crate::util::{self, Error};
+
+/// Output printed by the JVM when it fails before running the main class.
+const START_FAILURE_PATTERNS: [&str; 7] = [
+    "Error: Could not create the Java Virtual Machine",
+    "Error occurred during initialization of VM",
+    "Error: Could not find or load main class",
+    "Error: A JNI error has occurred",
+    "Unrecognized option:",
+    "Invalid maximum heap size",
+    "java.lang.UnsupportedClassVersionError",
+];
+
+const OUT_OF_MEMORY_PATTERNS: [&str; 3] = [
+    "java.lang.OutOfMemoryError",
+    "There is insufficient memory for the Java Runtime Environment",
+    "Out of Memory Error",
+];
+
+/// Signals raised by the JVM itself when it crashes, as opposed to being terminated.
+#[cfg(unix)]
+const CRASH_SIGNALS: [i32; 5] = [
+    4,  // SIGILL
+    6,  // SIGABRT
+    7,  // SIGBUS
+    8,  // SIGFPE
+    11, // SIGSEGV
+];
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum ExitKind {
+    /// The game exited with code 0.
+    Normal,
+    /// The game exited with an error code or crashed the JVM.
+    Crash,
+    /// The game was stopped by the launcher or terminated by a signal.
+    Killed,
+    /// The game ran out of memory.
+    OutOfMemory,
+    /// The JVM failed to start, e.g. because of bad JVM arguments or an incompatible SDK.
+    StartFailure,
+}
+
+impl ExitKind {
+    pub fn is_abnormal(&self) -> bool {
+        matches!(self, ExitKind::Crash | ExitKind::OutOfMemory | ExitKind::StartFailure)
+    }
+}
+
+/// Information about how a game process ended.
+pub struct ExitDetails {
+    pub(crate) kind: ExitKind,
+    pub(crate) code: Option<i32>,
+    pub(crate) signal: Option<i32>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CrashSummary {
+    /// Id of the profile, reports from before profile ids existed have its name instead.
+    pub(crate) profile: String,
+    #[serde(default)]
+    pub(crate) profile_name: String,
+    pub(crate) kind: ExitKind,
+    pub(crate) code: Option<i32>,
+    pub(crate) signal: Option<i32>,
+    /// Milliseconds since the unix epoch.
+    pub(crate) started_at: u64,
+    /// Milliseconds since the unix epoch.
+    pub(crate) exited_at: u64,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CrashReportInfo {
+    pub(crate) profile: String,
+    pub(crate) path: PathBuf,
+    pub(crate) size: u64,
+    pub(crate) summary: Option<CrashSummary>,
+}
+
+pub fn classify(status: Option<&ExitStatus>, killed: bool, tail: &[String], crash_files: &[PathBuf]) -> ExitDetails {
+    let code = status.and_then(|it| it.code());
+    let signal = status.and_then(signal_of);
+
+    let kind = if killed {
+        ExitKind::Killed
+    } else if code == Some(0) {
+        ExitKind::Normal
+    } else if contains_any(tail, &START_FAILURE_PATTERNS) {
+        ExitKind::StartFailure
+    } else if contains_any(tail, &OUT_OF_MEMORY_PATTERNS) || files_contain_any(crash_files, &OUT_OF_MEMORY_PATTERNS) {
+        ExitKind::OutOfMemory
+    } else if signal.map(is_termination_signal).unwrap_or(false) {
+        ExitKind::Killed
+    } else {
+        ExitKind::Crash
+    };
+
+    ExitDetails { kind, code, signal }
+}
+
+#[cfg(unix)]
+fn signal_of(status: &ExitStatus) -> Option<i32> {
+    use std::os::unix::process::ExitStatusExt;
+    status.signal()
+}
+
+#[cfg(not(unix))]
+fn signal_of(_status: &ExitStatus) -> Option<i32> {
+    None
+}
+
+#[cfg(unix)]
+fn is_termination_signal(signal: i32) -> bool {
+    !CRASH_SIGNALS.contains(&signal)
+}
+
+#[cfg(not(unix))]
+fn is_termination_signal(_signal: i32) -> bool {
+    false
+}
+
+fn contains_any(lines: &[String], patterns: &[&str]) -> bool {
+    lines.iter().any(|line| patterns.iter().any(|pattern| line.contains(pattern)))
+}
+
+fn files_contain_any(files: &[PathBuf], patterns: &[&str]) -> bool {
+    files.iter().any(|file| {
+        let mut content = String::new();
+        // Only the header of a JVM error log describes the reason.
+        match File::open(file).map(|it| it.take(16 * 1024).read_to_string(&mut content)) {
+            Ok(_) => patterns.iter().any(|pattern| content.contains(pattern)),
+            Err(_) => false,
+        }
+    })
+}
+
+/// Finds JVM error logs and game crash reports written since the game was started.
+pub fn find_crash_files(game_dir: &Path, started_at: u64) -> Vec<PathBuf> {
+    let mut files = vec![];
+
+    let mut candidates = vec![];
+    if let Ok(entries) = std::fs::read_dir(game_dir) {
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.starts_with("hs_err_pid") && name.ends_with(".log") {
+                candidates.push(entry.path());
+            }
+        }
+    }
+    if let Ok(entries) = std::fs::read_dir(game_dir.join("crash-reports")) {
+        for entry in entries.flatten() {
+            candidates.push(entry.path());
+        }
+    }
+
+    for path in candidates {
+        if !path.is_file() {
+            continue;
+        }
+        let modified = std::fs::metadata(&path)
+            .and_then(|it| it.modified())
+            .ok()
+            .and_then(|it| it.duration_since(UNIX_EPOCH).ok())
+            .map(|it| it.as_millis() as u64)
+            .unwrap_or(0);
+        if modified >= started_at {
+            files.push(path);
+        }
+    }
+
+    files.sort();
+    files
+}
+
+fn reports_dir() -> PathBuf {
+    util::get_data_dir().join("crash-reports")
+}
+
+/// Bundles the summary, the log tail and the crash files into `crash-reports/<profile id>/<timestamp>.zip`.
+pub fn write_report(summary: &CrashSummary, tail: &[String], crash_files: &[PathBuf]) -> Result<PathBuf, Error> {
+    let dir = reports_dir().join(util::sanitize_file_name(&summary.profile));
+    std::fs::create_dir_all(&dir)?;
+
+    let timestamp = chrono::Local::now().format("%Y-%m-%d_%H-%M-%S");
+    let path = dir.join(format!("{}.zip", timestamp));
+    let mut zip = ZipWriter::new(File::create(&path)?);
+    let options = FileOptions::default();
+
+    zip.start_file("summary.json", options)?;
+    serde_json::to_writer_pretty(&mut zip, summary)?;
+
+    zip.start_file("latest.log", options)?;
+    for line in tail {
+        writeln!(zip, "{}", line)?;
+    }
+
+    for file in crash_files {
+        let name = match file.file_name() {
+            Some(name) => name.to_string_lossy().to_string(),
+            None => continue,
+        };
+        zip.start_file(format!("files/{}", name), options)?;
+        io::copy(&mut File::open(file)?, &mut zip)?;
+    }
+
+    zip.finish()?;
+    println!("Wrote crash report: {}", path.to_string_lossy());
+    Ok(path)
+}
+
+/// Lists the crash reports of a profile, or of all profiles, newest first.
+pub fn list_reports(profile: Option<&str>) -> Result<Vec<CrashReportInfo>, Error> {
+    let root = reports_dir();
+    if !root.exists() {
+        return Ok(vec![]);
+    }
+
+    let mut dirs = vec![];
+    match profile {
+        Some(profile) => dirs.push(root.join(util::sanitize_file_name(profile))),
+        None => {
+            for entry in std::fs::read_dir(&root)? {
+                let path = entry?.path();
+                if path.is_dir() {
+                    dirs.push(path);
+                }
+            }
+        }
+    }
+
+    let mut reports = vec![];
+    for dir in dirs {
+        if !dir.exists() {
+            continue;
+        }
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().map(|it| it != "zip").unwrap_or(true) {
+                continue;
+            }
+            let summary = read_summary(&path).ok();
+            reports.push(CrashReportInfo {
+                profile: summary
+                    .as_ref()
+                    .map(|it| it.profile.to_string())
+                    .unwrap_or_else(|| dir.file_name().unwrap_or_default().to_string_lossy().to_string()),
+                size: std::fs::metadata(&path)?.len(),
+                path,
+                summary,
+            });
+        }
+    }
+
+    // Timestamps in the names sort chronologically.
+    reports.sort_by(|a, b| b.path.file_name().cmp(&a.path.file_name()));
+    Ok(reports)
+}
+
+fn read_summary(path: &Path) -> Result<CrashSummary, Error> {
+    let mut zip = ZipArchive::new(File::open(path)?)?;
+    let file = zip.by_name("summary.json")?;
+    Ok(serde_json::from_reader(file)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    fn exited(code: i32) -> ExitStatus {
+        use std::os::unix::process::ExitStatusExt;
+        ExitStatus::from_raw(code << 8)
+    }
+
+    #[cfg(unix)]
+    fn signalled(signal: i32) -> ExitStatus {
+        use std::os::unix::process::ExitStatusExt;
+        ExitStatus::from_raw(signal)
+    }
+
+    fn lines(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|it| it.to_string()).collect()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn normal_exit() {
+        let details = classify(Some(&exited(0)), false, &lines(&["Goodbye"]), &[]);
+        assert_eq!(details.kind, ExitKind::Normal);
+        assert_eq!(details.code, Some(0));
+        assert!(!details.kind.is_abnormal());
+    }
+
+    #[test]
+    fn killed_by_the_launcher() {
+        let details = classify(None, true, &lines(&["java.lang.OutOfMemoryError"]), &[]);
+        assert_eq!(details.kind, ExitKind::Killed);
+        assert!(!details.kind.is_abnormal());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn start_failure() {
+        let tail = lines(&["Error: Could not find or load main class com.example.Main"]);
+        assert_eq!(classify(Some(&exited(1)), false, &tail, &[]).kind, ExitKind::StartFailure);
+
+        let tail = lines(&["Unrecognized option: -XX:+Nonsense", "Error: Could not create the Java Virtual Machine."]);
+        assert_eq!(classify(Some(&exited(1)), false, &tail, &[]).kind, ExitKind::StartFailure);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn out_of_memory_from_the_log() {
+        let tail = lines(&["Exception in thread \"main\" java.lang.OutOfMemoryError: Java heap space"]);
+        assert_eq!(classify(Some(&exited(1)), false, &tail, &[]).kind, ExitKind::OutOfMemory);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn out_of_memory_from_an_error_log() {
+        let dir = util::test_dir("crash-oom");
+        let hs_err = dir.join("hs_err_pid1234.log");
+        std::fs::write(
+            &hs_err,
+            "#\n# There is insufficient memory for the Java Runtime Environment to continue.\n",
+        )
+        .unwrap();
+
+        let details = classify(Some(&signalled(6)), false, &lines(&["Rendering"]), &[hs_err]);
+        assert_eq!(details.kind, ExitKind::OutOfMemory);
+        assert_eq!(details.signal, Some(6));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn crash_signal_versus_termination_signal() {
+        let segfault = classify(Some(&signalled(11)), false, &[], &[]);
+        assert_eq!(segfault.kind, ExitKind::Crash);
+        assert_eq!(segfault.signal, Some(11));
+
+        let terminated = classify(Some(&signalled(15)), false, &[], &[]);
+        assert_eq!(terminated.kind, ExitKind::Killed);
+        assert_eq!(terminated.signal, Some(15));
+
+        assert_eq!(classify(Some(&exited(1)), false, &[], &[]).kind, ExitKind::Crash);
+    }
+}
@@ -13,4 +13,13 @@ pub struct GameConfig {
     pub(crate) sdk: SDK,
     pub(crate) main_class: String,
     pub(crate) game: String,
+    /// Default JVM arguments, may contain `${...}` launch variables.
+    #[serde(default)]
+    pub(crate) jvm_args: Vec<String>,
+    /// Default program arguments, may contain `${...}` launch variables.
+    #[serde(default)]
+    pub(crate) game_args: Vec<String>,
+    /// Files and directories of the shared game directory copied into a new isolated instance.
+    #[serde(default)]
+    pub(crate) instance_seed: Vec<String>,
 }
@@ -0,0 +1,265 @@
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use semver::{Version, VersionReq};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::archive;
+use crate::profiles::{self, Profile};
+use crate::sdk::{self, INSTALL_MARKER};
+use crate::util::{self, Error};
+
+/// An SDK installed by the launcher under `sdks/<type>/<version>`.
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct InstalledSdk {
+    pub(crate) sdk_type: String,
+    pub(crate) version: Version,
+    pub(crate) path: PathBuf,
+    /// Size on disk in bytes.
+    pub(crate) size: u64,
+    /// Milliseconds since the unix epoch.
+    pub(crate) installed_at: u64,
+    pub(crate) url: String,
+    /// Names of the profiles whose version range, or SDK override, matches this SDK.
+    pub(crate) used_by: Vec<String>,
+}
+
+/// Outcome of re-hashing an installed SDK.
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SdkVerification {
+    pub(crate) valid: bool,
+    pub(crate) expected: String,
+    pub(crate) actual: String,
+}
+
+/// SDK type and version range a profile needs, taking its SDK override into account.
+struct Requirement {
+    profile: String,
+    sdk_type: String,
+    versions: VersionReq,
+}
+
+fn sdks_root() -> PathBuf {
+    util::get_data_dir().join("sdks")
+}
+
+/// Install directory of an SDK, the type and version come from the UI so they're checked first.
+fn install_dir(sdk_type: &str, version: &Version) -> Result<PathBuf, Error> {
+    if sdk_type.is_empty() || sdk_type.starts_with('.') || sdk_type.contains(['/', '\\']) {
+        return Err(Error::msg(&format!("Invalid SDK type: '{}'", sdk_type)));
+    }
+    archive::safe_join(&sdks_root(), &format!("{}/{}", sdk_type, version))
+}
+
+fn requirements(profiles: &[Profile]) -> Result<Vec<Requirement>, Error> {
+    let mut requirements = vec![];
+    for profile in profiles {
+        let versions = match &profile.settings.sdk_override {
+            Some(sdk_override) => match sdk_override.version_req() {
+                Some(versions) => Some(versions),
+                // Profiles using an SDK by path don't need any installed SDK
+                None => continue,
+            },
+            None => None,
+        };
+        let cfg = profiles::read_cfg(&profile.version_dir()).map_err(|e| {
+            Error::msg(&format!("Failed to read version config of profile '{}': {:?}", profile.name, e))
+        })?;
+        requirements.push(Requirement {
+            profile: profile.name.to_string(),
+            versions: match versions {
+                Some(versions) => versions,
+                None => util::get_version_req(&cfg)?,
+            },
+            sdk_type: cfg.sdk.r#type,
+        });
+    }
+    Ok(requirements)
+}
+
+/// Lists every completely installed SDK, along with the profiles that can use it.
+pub fn list(profiles: &[Profile]) -> Result<Vec<InstalledSdk>, Error> {
+    let requirements = requirements(profiles)?;
+    let mut installed = vec![];
+    for (sdk_type, version, path) in install_dirs()? {
+        let marker = match sdk::read_or_migrate_marker(&path) {
+            Ok(marker) => marker,
+            Err(_) => continue,
+        };
+        let used_by = requirements
+            .iter()
+            .filter(|it| it.sdk_type == sdk_type && it.versions.matches(&version))
+            .map(|it| it.profile.to_string())
+            .collect();
+        installed.push(InstalledSdk {
+            size: dir_size(&path)?,
+            installed_at: marker.installed_at,
+            url: marker.url,
+            sdk_type,
+            version,
+            path,
+            used_by,
+        });
+    }
+    installed.sort_by(|a, b| a.sdk_type.cmp(&b.sdk_type).then(b.version.cmp(&a.version)));
+    Ok(installed)
+}
+
+/// Every `sdks/<type>/<version>` directory, complete or not.
+fn install_dirs() -> Result<Vec<(String, Version, PathBuf)>, Error> {
+    let root = sdks_root();
+    let mut dirs = vec![];
+    if !root.exists() {
+        return Ok(dirs);
+    }
+    for type_entry in std::fs::read_dir(root)? {
+        let type_entry = type_entry?;
+        let sdk_type = type_entry.file_name().to_string_lossy().to_string();
+        // The staging directory isn't an SDK type
+        if sdk_type.starts_with('.') || !type_entry.file_type()?.is_dir() {
+            continue;
+        }
+        for entry in std::fs::read_dir(type_entry.path())? {
+            let entry = entry?;
+            if let Ok(version) = Version::parse(&entry.file_name().to_string_lossy()) {
+                dirs.push((sdk_type.to_string(), version, entry.path()));
+            }
+        }
+    }
+    Ok(dirs)
+}
+
+/// Re-hashes an installed SDK and compares it with the hash recorded when it was installed.
+pub fn verify(sdk_type: &str, version: &Version) -> Result<SdkVerification, Error> {
+    let dir = install_dir(sdk_type, version)?;
+    let marker = sdk::read_marker(&dir)
+        .map_err(|e| Error::msg(&format!("SDK {} {} is not installed: {}", sdk_type, version, e)))?;
+    let expected = marker.tree_sha256.ok_or_else(|| {
+        Error::msg(&format!(
+            "SDK {} {} was installed without a recorded hash, reinstall it to verify it",
+            sdk_type, version
+        ))
+    })?;
+    let actual = tree_hash(&dir)?;
+    Ok(SdkVerification {
+        valid: expected.eq_ignore_ascii_case(&actual),
+        expected,
+        actual,
+    })
+}
+
+pub fn remove(sdk_type: &str, version: &Version) -> Result<(), Error> {
+    let dir = install_dir(sdk_type, version)?;
+    if !dir.exists() {
+        return Err(Error::msg(&format!("SDK {} {} is not installed", sdk_type, version)));
+    }
+    println!("Removing SDK: {}", dir.to_string_lossy());
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+/// Removes every SDK that no profile can resolve to, as well as incomplete installs.
+///
+/// Fails without removing anything when a profile's config can't be read, as its SDK would be
+/// removed otherwise.
+pub fn gc(profiles: &[Profile]) -> Result<Vec<InstalledSdk>, Error> {
+    let requirements = requirements(profiles)?;
+    let mut removed = vec![];
+    for (sdk_type, version, path) in install_dirs()? {
+        let marker = sdk::read_or_migrate_marker(&path).ok();
+        let used = requirements
+            .iter()
+            .any(|it| it.sdk_type == sdk_type && it.versions.matches(&version));
+        if used && marker.is_some() {
+            continue;
+        }
+
+        let size = dir_size(&path)?;
+        println!("Removing unused SDK: {}", path.to_string_lossy());
+        std::fs::remove_dir_all(&path)?;
+        removed.push(InstalledSdk {
+            installed_at: marker.as_ref().map(|it| it.installed_at).unwrap_or(0),
+            url: marker.map(|it| it.url).unwrap_or_default(),
+            sdk_type,
+            version,
+            path,
+            size,
+            used_by: vec![],
+        });
+    }
+    Ok(removed)
+}
+
+fn dir_size(dir: &Path) -> Result<u64, Error> {
+    let mut size = 0;
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.path().symlink_metadata()?;
+        size += if metadata.is_dir() { dir_size(&entry.path())? } else { metadata.len() };
+    }
+    Ok(size)
+}
+
+/// SHA-256 over the relative paths, kinds and contents of everything in the SDK directory except
+/// the install marker, in a stable order.
+pub fn tree_hash(dir: &Path) -> Result<String, Error> {
+    let mut entries = vec![];
+    collect_entries(dir, dir, &mut entries)?;
+    entries.sort();
+
+    let mut hasher = Sha256::new();
+    for relative in entries {
+        let path = dir.join(&relative);
+        let metadata = path.symlink_metadata()?;
+        hasher.update(relative.as_bytes());
+        hasher.update([0]);
+        if metadata.file_type().is_symlink() {
+            hasher.update(b"l");
+            hasher.update(std::fs::read_link(&path)?.to_string_lossy().as_bytes());
+        } else if metadata.is_dir() {
+            hasher.update(b"d");
+        } else {
+            hasher.update(if is_executable(&metadata) { b"x" } else { b"f" });
+            io::copy(&mut File::open(&path)?, &mut hasher)?;
+        }
+        hasher.update([0]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Relative paths with `/` separators, so the hash doesn't depend on the platform.
+fn collect_entries(root: &Path, dir: &Path, entries: &mut Vec<String>) -> Result<(), Error> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let relative = path
+            .strip_prefix(root)
+            .map_err(|e| Error::msg(&e.to_string()))?
+            .components()
+            .map(|it| it.as_os_str().to_string_lossy().to_string())
+            .collect::<Vec<_>>()
+            .join("/");
+        if relative == INSTALL_MARKER {
+            continue;
+        }
+        if path.symlink_metadata()?.is_dir() {
+            collect_entries(root, &path, entries)?;
+        }
+        entries.push(relative);
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn is_executable(metadata: &std::fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(_metadata: &std::fs::Metadata) -> bool {
+    false
+}
@@ -0,0 +1,96 @@
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::archive;
+use crate::game::GameConfig;
+use crate::profiles::{GameDirMode, Profile};
+use crate::util::{self, Error};
+
+/// Written into an instance directory when it is created.
+const INSTANCE_MARKER: &str = ".instance.json";
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct InstanceMarker<'a> {
+    profile: Uuid,
+    game: &'a str,
+    version: &'a str,
+    /// Milliseconds since the unix epoch.
+    created_at: u64,
+}
+
+/// `games/<game>`, shared by every profile of the game.
+pub fn shared_dir(data_dir: &Path, cfg: &GameConfig) -> PathBuf {
+    data_dir.join("games").join(&cfg.game)
+}
+
+/// `instances/<profile id>`, whether or not the profile uses it.
+pub fn instance_dir(data_dir: &Path, profile: &Profile) -> PathBuf {
+    data_dir.join("instances").join(profile.id.to_string())
+}
+
+/// Working directory of the game for a profile.
+pub fn game_dir(data_dir: &Path, cfg: &GameConfig, profile: &Profile) -> PathBuf {
+    match profile.settings.game_dir_mode {
+        GameDirMode::Shared => shared_dir(data_dir, cfg),
+        GameDirMode::Isolated => instance_dir(data_dir, profile),
+    }
+}
+
+/// Creates the game directory of a profile if needed, seeding new instances from the shared
+/// directory with the entries listed in the game config.
+pub fn prepare(data_dir: &Path, cfg: &GameConfig, profile: &Profile) -> Result<PathBuf, Error> {
+    let dir = game_dir(data_dir, cfg, profile);
+    if profile.settings.game_dir_mode == GameDirMode::Shared || dir.join(INSTANCE_MARKER).exists() {
+        std::fs::create_dir_all(&dir)?;
+        return Ok(dir);
+    }
+
+    println!("Creating instance directory: {}", dir.to_string_lossy());
+    std::fs::create_dir_all(&dir)?;
+    let shared = shared_dir(data_dir, cfg);
+    for entry in cfg.instance_seed.iter() {
+        // Seed entries come from the game package, so they must stay inside both directories.
+        let source = archive::safe_join(&shared, entry)?;
+        if !source.exists() {
+            continue;
+        }
+        let target = archive::safe_join(&dir, entry)?;
+        if target.exists() {
+            continue;
+        }
+        println!("Seeding instance with: {}", entry);
+        copy_recursive(&source, &target)?;
+    }
+
+    // Written last, so an interrupted seed is retried on the next launch.
+    let marker = InstanceMarker {
+        profile: profile.id,
+        game: &cfg.game,
+        version: &profile.version,
+        created_at: util::now_millis(),
+    };
+    std::fs::write(dir.join(INSTANCE_MARKER), serde_json::to_vec_pretty(&marker)?)?;
+    Ok(dir)
+}
+
+fn copy_recursive(source: &Path, target: &Path) -> Result<(), Error> {
+    let metadata = source.symlink_metadata()?;
+    if metadata.is_dir() {
+        std::fs::create_dir_all(target)?;
+        for entry in std::fs::read_dir(source)? {
+            let entry = entry?;
+            copy_recursive(&entry.path(), &target.join(entry.file_name()))?;
+        }
+    } else if metadata.is_file() {
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::copy(source, target)?;
+    } else {
+        println!("Not seeding special file: {}", source.to_string_lossy());
+    }
+    Ok(())
+}
@@ -0,0 +1,344 @@
+use std::collections::HashSet;
+use std::env::consts::ARCH;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use semver::{Version, VersionReq};
+use serde::Serialize;
+
+use crate::sdk::{self, ResolvedSdk, SDKPlatform};
+use crate::util::Error;
+
+/// SDK types that can be satisfied by a JDK installed outside of the launcher.
+const JAVA_SDK_TYPES: [&str; 3] = ["java", "jdk", "jre"];
+
+/// How long a JDK gets to print its properties before it's considered broken.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where a local JDK was found.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum JdkSource {
+    JavaHome,
+    System,
+    Sdkman,
+    IntellijJdks,
+    /// Path set as a profile's SDK override.
+    Profile,
+}
+
+/// A JDK that was installed by something other than the launcher.
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LocalJdk {
+    pub(crate) home: PathBuf,
+    pub(crate) version: Version,
+    pub(crate) vendor: String,
+    /// Architecture reported by the JDK, normalised to Rust's names (e.g. `x86_64`, `aarch64`).
+    pub(crate) arch: String,
+    pub(crate) source: JdkSource,
+}
+
+impl LocalJdk {
+    /// Whether the JDK can run here, natively or under emulation.
+    pub fn matches_host(&self) -> bool {
+        match SDKPlatform::current() {
+            Ok(platforms) => platforms.iter().any(|it| it.arch() == self.arch),
+            Err(_) => self.arch == ARCH,
+        }
+    }
+
+    pub fn resolve(&self) -> ResolvedSdk {
+        ResolvedSdk {
+            version: self.version.to_owned(),
+            executable: self.home.join(sdk::default_executable()),
+            home: self.home.to_owned(),
+        }
+    }
+}
+
+/// Local JDKs found by the last discovery, as probing every JDK takes a while.
+#[derive(Default)]
+pub struct LocalJdks(Mutex<Option<Vec<LocalJdk>>>);
+
+impl LocalJdks {
+    /// The cached JDKs, discovering them first when there are none yet or `refresh` is set.
+    pub async fn get(&self, refresh: bool) -> Result<Vec<LocalJdk>, Error> {
+        if !refresh {
+            if let Some(jdks) = self.0.lock()?.as_ref() {
+                return Ok(jdks.clone());
+            }
+        }
+        let jdks = tauri::async_runtime::spawn_blocking(discover)
+            .await
+            .map_err(|e| Error::msg(&format!("Failed to discover local JDKs: {}", e)))?;
+        *self.0.lock()? = Some(jdks.clone());
+        Ok(jdks)
+    }
+}
+
+pub fn supports_type(sdk_type: &str) -> bool {
+    JAVA_SDK_TYPES.iter().any(|it| it.eq_ignore_ascii_case(sdk_type))
+}
+
+/// Finds and probes every JDK in the well-known install locations, skipping broken ones.
+pub fn discover() -> Vec<LocalJdk> {
+    let mut seen = HashSet::new();
+    let mut jdks = vec![];
+    for (source, candidate) in candidates() {
+        let home = match find_home(&candidate) {
+            Some(home) => home,
+            None => continue,
+        };
+        // SDKMAN's `current` and distro alternatives are symlinks to JDKs we already know.
+        if !seen.insert(std::fs::canonicalize(&home).unwrap_or_else(|_| home.to_owned())) {
+            continue;
+        }
+        match probe(&home, source) {
+            Ok(jdk) => jdks.push(jdk),
+            Err(e) => println!("Ignoring local JDK at {}: {}", home.to_string_lossy(), e),
+        }
+    }
+    jdks
+}
+
+/// Picks the highest of the discovered JDKs matching the SDK type and version range.
+pub fn find_compatible(jdks: &[LocalJdk], sdk_type: &str, versions: &VersionReq) -> Option<ResolvedSdk> {
+    if !supports_type(sdk_type) {
+        return None;
+    }
+    let jdk = jdks
+        .iter()
+        .filter(|it| it.matches_host() && versions.matches(&it.version))
+        .max_by(|a, b| a.version.cmp(&b.version))?;
+    println!(
+        "Found local JDK {} ({}) at {}",
+        jdk.version,
+        jdk.vendor,
+        jdk.home.to_string_lossy()
+    );
+    Some(jdk.resolve())
+}
+
+/// Directories that may contain a JDK, in order of preference.
+fn candidates() -> Vec<(JdkSource, PathBuf)> {
+    let mut candidates = vec![];
+    if let Some(java_home) = std::env::var_os("JAVA_HOME") {
+        candidates.push((JdkSource::JavaHome, PathBuf::from(java_home)));
+    }
+
+    let home = std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(PathBuf::from);
+
+    let sdkman_dir = std::env::var_os("SDKMAN_DIR")
+        .map(PathBuf::from)
+        .or_else(|| home.as_ref().map(|it| it.join(".sdkman")));
+    if let Some(dir) = sdkman_dir {
+        candidates.extend(children(&dir.join("candidates/java"), JdkSource::Sdkman));
+    }
+    if let Some(home) = &home {
+        candidates.extend(children(&home.join(".jdks"), JdkSource::IntellijJdks));
+        #[cfg(target_os = "macos")]
+        candidates.extend(children(&home.join("Library/Java/JavaVirtualMachines"), JdkSource::System));
+    }
+
+    for dir in system_dirs() {
+        candidates.extend(children(&dir, JdkSource::System));
+    }
+    candidates
+}
+
+#[cfg(target_os = "linux")]
+fn system_dirs() -> Vec<PathBuf> {
+    ["/usr/lib/jvm", "/usr/lib64/jvm", "/usr/java", "/opt/java"]
+        .iter()
+        .map(PathBuf::from)
+        .collect()
+}
+
+#[cfg(target_os = "macos")]
+fn system_dirs() -> Vec<PathBuf> {
+    vec![PathBuf::from("/Library/Java/JavaVirtualMachines")]
+}
+
+#[cfg(target_os = "windows")]
+fn system_dirs() -> Vec<PathBuf> {
+    let program_files = std::env::var_os("ProgramFiles")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("C:\\Program Files"));
+    ["Java", "Eclipse Adoptium", "Microsoft", "Zulu", "BellSoft", "Amazon Corretto"]
+        .iter()
+        .map(|it| program_files.join(it))
+        .collect()
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+fn system_dirs() -> Vec<PathBuf> {
+    vec![]
+}
+
+fn children(dir: &Path, source: JdkSource) -> Vec<(JdkSource, PathBuf)> {
+    let mut children: Vec<PathBuf> = match std::fs::read_dir(dir) {
+        Ok(entries) => entries.flatten().map(|it| it.path()).collect(),
+        Err(_) => return vec![],
+    };
+    children.sort();
+    children.into_iter().map(|it| (source, it)).collect()
+}
+
+/// macOS bundles keep the actual home in `Contents/Home`.
+fn find_home(dir: &Path) -> Option<PathBuf> {
+    [dir.to_path_buf(), dir.join("Contents/Home")]
+        .into_iter()
+        .find(|it| it.join(probe_executable()).is_file())
+}
+
+/// `javaw` doesn't have a console, so probing always uses `java`.
+fn probe_executable() -> &'static str {
+    if cfg!(target_os = "windows") {
+        "bin/java.exe"
+    } else {
+        "bin/java"
+    }
+}
+
+/// Runs `java -XshowSettings:properties -version` and reads the version, vendor and architecture.
+pub fn probe(home: &Path, source: JdkSource) -> Result<LocalJdk, Error> {
+    let executable = home.join(probe_executable());
+    let mut child = Command::new(&executable)
+        .arg("-XshowSettings:properties")
+        .arg("-version")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // Read while the JDK runs, it blocks once the pipe buffer is full, which is small on Windows.
+    let mut stderr = child
+        .stderr
+        .take()
+        .ok_or_else(|| Error::msg("Failed to read the JDK's properties"))?;
+    let reader = std::thread::spawn(move || {
+        let mut output = String::new();
+        stderr.read_to_string(&mut output).map(|_| output)
+    });
+
+    let started = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if started.elapsed() > PROBE_TIMEOUT {
+            child.kill()?;
+            child.wait()?;
+            return Err(Error::msg("Timed out while reading the JDK's properties"));
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    };
+
+    let output = reader
+        .join()
+        .map_err(|_| Error::msg("Failed to read the JDK's properties"))??;
+    if !status.success() {
+        return Err(Error::msg(&format!("'-XshowSettings:properties -version' exited with {}", status)));
+    }
+
+    let property = |key: &str| {
+        output.lines().find_map(|line| {
+            let (name, value) = line.trim().split_once(" = ")?;
+            (name == key).then(|| value.trim().to_string())
+        })
+    };
+    let raw_version = property("java.version").ok_or_else(|| Error::msg("Missing 'java.version' property"))?;
+    let version = parse_java_version(&raw_version)
+        .ok_or_else(|| Error::msg(&format!("Unsupported Java version: {}", raw_version)))?;
+    let arch = property("os.arch").ok_or_else(|| Error::msg("Missing 'os.arch' property"))?;
+
+    Ok(LocalJdk {
+        home: home.to_path_buf(),
+        version,
+        vendor: property("java.vendor").unwrap_or_default(),
+        arch: normalize_arch(&arch).to_string(),
+        source,
+    })
+}
+
+/// Converts `java.version` to semver, e.g. `1.8.0_392` to `8.0.392` and `21-ea` to `21.0.0`.
+fn parse_java_version(raw: &str) -> Option<Version> {
+    let numeric: String = raw
+        .chars()
+        .take_while(|it| it.is_ascii_digit() || *it == '.' || *it == '_')
+        .collect();
+    let mut parts: Vec<u64> = numeric
+        .split(['.', '_'])
+        .filter(|it| !it.is_empty())
+        .map(|it| it.parse().ok())
+        .collect::<Option<_>>()?;
+    if parts.is_empty() {
+        return None;
+    }
+    // Java 8 and older report themselves as 1.x
+    if parts.len() > 1 && parts[0] == 1 {
+        parts.remove(0);
+    }
+    parts.resize(3.max(parts.len()), 0);
+    Some(Version::new(parts[0], parts[1], parts[2]))
+}
+
+fn normalize_arch(arch: &str) -> &str {
+    match arch {
+        "amd64" | "x86_64" => "x86_64",
+        "x86" | "i386" | "i486" | "i586" | "i686" => "x86",
+        "aarch64" | "arm64" => "aarch64",
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util;
+
+    /// JDK home whose `java` prints a lot of noise before the properties, more than a pipe holds.
+    #[cfg(unix)]
+    fn fake_jdk(name: &str) -> PathBuf {
+        use std::os::unix::fs::PermissionsExt;
+
+        let home = util::test_dir(name);
+        std::fs::create_dir_all(home.join("bin")).unwrap();
+        let script = home.join(probe_executable());
+        std::fs::write(
+            &script,
+            "#!/bin/sh\n\
+             i=0; while [ $i -lt 4000 ]; do echo \"    noise.$i = padding padding padding padding\" >&2; i=$((i+1)); done\n\
+             echo '    java.version = 17.0.9' >&2\n\
+             echo '    java.vendor = Test' >&2\n\
+             echo '    os.arch = amd64' >&2\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        home
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn probe_reads_large_output() {
+        let home = fake_jdk("jdks-probe");
+        let jdk = probe(&home, JdkSource::Profile).unwrap();
+        assert_eq!(jdk.version, Version::new(17, 0, 9));
+        assert_eq!(jdk.vendor, "Test");
+        assert_eq!(jdk.arch, "x86_64");
+    }
+
+    #[test]
+    fn parses_java_versions() {
+        assert_eq!(parse_java_version("1.8.0_392"), Some(Version::new(8, 0, 392)));
+        assert_eq!(parse_java_version("17.0.9"), Some(Version::new(17, 0, 9)));
+        assert_eq!(parse_java_version("21-ea"), Some(Version::new(21, 0, 0)));
+        assert_eq!(parse_java_version("ea"), None);
+    }
+}
@@ -1,87 +1,290 @@
-#[cfg(target_os = "windows")]
-use std::os::windows::process::CommandExt;
-use std::path::PathBuf;
-use std::process::{exit, ExitStatus, Stdio};
-use std::process;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
 
-use tauri::Window;
+use tauri::{AppHandle, Window};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process;
+use tauri::async_runtime::JoinHandle;
 
 use crate::game::GameConfig;
-use crate::sdk::SDKInfo;
+use crate::instances;
+use crate::logs::{GameLog, LogStream};
+use crate::profiles::Profile;
+use crate::running::{self, RunningGameInfo};
+use crate::sdk::ResolvedSdk;
 use crate::util::{Error, PATH_SEPARATOR};
 
 #[cfg(target_os = "windows")]
 const DETACHED_PROCESS: u32 = 0x00000008;
 
-pub fn run_with_sdk(window: &Window, sdk_info: &SDKInfo, cfg: &GameConfig, data_dir: &String, cp: Vec<String>) -> Result<i32, Result<i32, Error>> {
-    let sdk_path = prepare_run(sdk_info, cfg, data_dir);
+pub fn run_with_sdk(app: &AppHandle, window: &Window, sdk: &ResolvedSdk, cfg: &GameConfig, profile: &Profile, data_dir: &str, cp: Vec<String>) -> Result<RunningGameInfo, Error> {
+    let sdk_path = prepare_run(sdk)?;
 
     println!("Running SDK: {}", sdk_path.to_string_lossy());
 
+    if let Err(e) = window.hide() {
+        println!("Failed to hide window: {:?}", e);
+    }
+
     let cp = cp.join(PATH_SEPARATOR);
-    let code = match run_game(data_dir, &cp, cfg, sdk_path) {
-        Ok(v) => v.code().unwrap_or(0),
-        Err(e) => {
-            window.show().expect("Failed to show window again.");
-            return Err(Err(Error::Launch(format!("Game Crashed: {:?}", e))));
+    run_game(app, window, data_dir, &cp, cfg, profile, sdk_path).map_err(|e| {
+        if let Err(e) = window.show() {
+            println!("Failed to show window again: {:?}", e);
+        }
+        Error::Launch(format!("Failed to start game: {:?}", e))
+    })
+}
+
+fn prepare_run(sdk: &ResolvedSdk) -> Result<PathBuf, Error> {
+    validate_executable(&sdk.executable)?;
+    Ok(sdk.executable.to_path_buf())
+}
+
+/// Runs `<executable> -version` to make sure a freshly installed SDK actually works.
+pub fn check_runnable(sdk: &ResolvedSdk) -> Result<(), Error> {
+    validate_executable(&sdk.executable)?;
+    let status = std::process::Command::new(&sdk.executable)
+        .arg("-version")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map_err(|e| Error::Launch(format!("Failed to run SDK {}: {}", sdk.executable.to_string_lossy(), e)))?;
+    if !status.success() {
+        return Err(Error::Launch(format!(
+            "SDK {} is not runnable, '-version' exited with {}",
+            sdk.executable.to_string_lossy(),
+            status
+        )));
+    }
+    Ok(())
+}
+
+/// Checks that the SDK executable exists and can be executed by the current user.
+pub fn validate_executable(path: &Path) -> Result<(), Error> {
+    let metadata = std::fs::metadata(path).map_err(|e| {
+        Error::Launch(format!("SDK executable not found at {}: {}", path.to_string_lossy(), e))
+    })?;
+    if !metadata.is_file() {
+        return Err(Error::Launch(format!("SDK executable is not a file: {}", path.to_string_lossy())));
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if metadata.permissions().mode() & 0o111 == 0 {
+            return Err(Error::Launch(format!("SDK executable is not executable: {}", path.to_string_lossy())));
         }
-    };
+    }
+    Ok(())
+}
 
-    if code == 0 {
-        exit(0);
+/// Variables available to `${...}` placeholders in launch arguments.
+pub struct LaunchVariables(HashMap<&'static str, String>);
+
+impl LaunchVariables {
+    pub fn new(data_dir: &str, game_dir: &Path, cp: &str, cfg: &GameConfig, profile: &Profile) -> LaunchVariables {
+        let data_path = Path::new(data_dir);
+        let mut vars = HashMap::new();
+        vars.insert("data_dir", data_dir.to_string());
+        vars.insert("game_dir", game_dir.to_string_lossy().to_string());
+        vars.insert("shared_game_dir", instances::shared_dir(data_path, cfg).to_string_lossy().to_string());
+        vars.insert("instance_dir", instances::instance_dir(data_path, profile).to_string_lossy().to_string());
+        vars.insert("version", profile.version.to_string());
+        vars.insert("profile_id", profile.id.to_string());
+        vars.insert("profile_name", profile.name.to_string());
+        vars.insert("classpath", cp.to_string());
+        vars.insert("main_class", cfg.main_class.to_string());
+        vars.insert("game", cfg.game.to_string());
+        LaunchVariables(vars)
+    }
+
+    /// Replaces every known `${name}` placeholder, unknown ones are kept as-is.
+    pub fn expand(&self, template: &str) -> String {
+        let mut result = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(start) = rest.find("${") {
+            result.push_str(&rest[..start]);
+            let after = &rest[start + 2..];
+            match after.find('}') {
+                Some(end) => {
+                    let name = &after[..end];
+                    match self.0.get(name) {
+                        Some(value) => result.push_str(value),
+                        None => {
+                            println!("Unknown launch variable: ${{{}}}", name);
+                            result.push_str(&rest[start..start + 2 + end + 1]);
+                        }
+                    }
+                    rest = &after[end + 1..];
+                }
+                None => {
+                    result.push_str(&rest[start..]);
+                    rest = "";
+                }
+            }
+        }
+        result.push_str(rest);
+        result
+    }
+
+    pub fn expand_all(&self, templates: &[String]) -> Vec<String> {
+        templates.iter().map(|it| self.expand(it)).collect()
     }
-    Ok(code)
 }
 
-fn prepare_run(sdk_info: &SDKInfo, cfg: &GameConfig, data_dir: &String) -> PathBuf {
-    let mut sdk_path =
-        PathBuf::from(data_dir).join(format!("sdks/{}/{}/", cfg.sdk.r#type, sdk_info.version));
-    if sdk_info.inner_path.is_some() {
-        let inner_path = sdk_info.inner_path.as_ref().unwrap();
-        sdk_path = sdk_path.join(inner_path);
+/// Builds the full argument list: game defaults first, so profile overrides win.
+fn build_args(cp: &str, cfg: &GameConfig, profile: &Profile, vars: &LaunchVariables) -> Vec<String> {
+    let profile_jvm_args = profile.settings.jvm_options();
+    let mut args = vars.expand_all(&cfg.jvm_args);
+    args.extend(vars.expand_all(&profile_jvm_args));
+
+    // Games and profiles that declare the classpath themselves don't need it twice.
+    if !cfg.jvm_args.iter().chain(profile_jvm_args.iter()).any(|it| it.contains("${classpath}")) {
+        args.push("-cp".to_string());
+        args.push(cp.to_string());
     }
 
-    sdk_path = sdk_path.join("bin/java");
-    sdk_path
+    args.push(cfg.main_class.to_string());
+    args.extend(vars.expand_all(&cfg.game_args));
+    args.extend(vars.expand_all(&profile.settings.game_args));
+    args
 }
 
-//noinspection DuplicatedCode
 fn run_game(
-    data_dir: &String,
-    cp: &String,
+    app: &AppHandle,
+    window: &Window,
+    data_dir: &str,
+    cp: &str,
     cfg: &GameConfig,
+    profile: &Profile,
     sdk_path: PathBuf,
-) -> Result<ExitStatus, Error> {
-    #[allow(unused_mut)]
-    #[cfg(target_os = "linux")]
-    let status = process::Command::new(sdk_path)
-        .args(["-cp", &cp, &cfg.main_class])
-        .stderr(Stdio::inherit())
-        .stdout(Stdio::inherit())
-        .stdin(Stdio::inherit())
-        .current_dir((&data_dir).to_string() + "/games/" + &cfg.game)
-        .spawn()?.wait()?;
-
-    #[allow(unused_mut)]
-    #[cfg(target_os = "macos")]
-        let status = process::Command::new(sdk_path)
-        .args(["-cp", &cp, &cfg.main_class])
-        .stderr(Stdio::inherit())
-        .stdout(Stdio::inherit())
+) -> Result<RunningGameInfo, Error> {
+    let game_dir = instances::prepare(Path::new(data_dir), cfg, profile)?;
+    let vars = LaunchVariables::new(data_dir, &game_dir, cp, cfg, profile);
+    let args = build_args(cp, cfg, profile, &vars);
+    println!("Launch arguments: {:?}", args);
+
+    let log = Arc::new(GameLog::create(&profile.id.to_string())?);
+
+    let mut command = process::Command::new(sdk_path);
+    command
+        .args(args)
+        .stderr(Stdio::piped())
+        .stdout(Stdio::piped())
         .stdin(Stdio::inherit())
-        .current_dir((&data_dir).to_string() + "/games/" + &cfg.game)
-        .spawn()?.wait()?;
+        .current_dir(&game_dir);
 
-    #[allow(unused_mut)]
     #[cfg(target_os = "windows")]
-        let status = process::Command::new(sdk_path)
-        .args(["-cp", &cp, &cfg.main_class])
-        .stderr(Stdio::inherit())
-        .stdout(Stdio::inherit())
-        .stdin(Stdio::inherit())
-        .current_dir((&data_dir).to_string() + "/games/" + &cfg.game)
-        .creation_flags(DETACHED_PROCESS)
-        .spawn()?.wait()?;
+    command.creation_flags(DETACHED_PROCESS);
 
-    Ok(status)
+    let mut child = command.spawn()?;
+    let stdout = child.stdout.take().map(|it| pipe_log(app, &log, LogStream::Stdout, it));
+    let stderr = child.stderr.take().map(|it| pipe_log(app, &log, LogStream::Stderr, it));
+
+    let readers = [stdout, stderr].into_iter().flatten().collect();
+    running::watch(app, window, profile, child, readers, log, game_dir)
+}
+
+/// Forwards every line of the child's output stream to the game log.
+fn pipe_log<R: AsyncRead + Unpin + Send + 'static>(
+    app: &AppHandle,
+    log: &Arc<GameLog>,
+    stream: LogStream,
+    reader: R,
+) -> JoinHandle<()> {
+    let app = app.to_owned();
+    let log = log.to_owned();
+    tauri::async_runtime::spawn(async move {
+        let mut lines = BufReader::new(reader).lines();
+        loop {
+            match lines.next_line().await {
+                Ok(Some(line)) => log.log(&app, stream, line),
+                Ok(None) => break,
+                Err(e) => {
+                    println!("Failed to read game output: {:?}", e);
+                    break;
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn vars() -> LaunchVariables {
+        LaunchVariables(HashMap::from([
+            ("classpath", "a.jar:b.jar".to_string()),
+            ("game_dir", "/games/test".to_string()),
+            ("version", "1.0.0".to_string()),
+        ]))
+    }
+
+    fn config(jvm_args: &[&str]) -> GameConfig {
+        serde_json::from_value(serde_json::json!({
+            "classpath": ["a.jar"],
+            "sdk": {"version": "17", "type": "java"},
+            "main-class": "com.example.Main",
+            "game": "test",
+            "jvm-args": jvm_args,
+        }))
+        .unwrap()
+    }
+
+    fn profile(jvm_args: &[&str]) -> Profile {
+        let mut profile = Profile {
+            id: Uuid::new_v4(),
+            game: "test".to_string(),
+            name: "Test".to_string(),
+            version: "1.0.0".to_string(),
+            settings: Default::default(),
+        };
+        profile.settings.jvm_args = jvm_args.iter().map(|it| it.to_string()).collect();
+        profile
+    }
+
+    #[test]
+    fn expands_known_variables() {
+        assert_eq!(vars().expand("-Dgame.dir=${game_dir}"), "-Dgame.dir=/games/test");
+        assert_eq!(vars().expand("${version}"), "1.0.0");
+        assert_eq!(vars().expand("no placeholders"), "no placeholders");
+    }
+
+    #[test]
+    fn keeps_unknown_variables() {
+        assert_eq!(vars().expand("-Dx=${unknown}"), "-Dx=${unknown}");
+        assert_eq!(vars().expand("${unknown}-${version}"), "${unknown}-1.0.0");
+    }
+
+    #[test]
+    fn keeps_unterminated_placeholders() {
+        assert_eq!(vars().expand("-Dx=${version"), "-Dx=${version");
+        assert_eq!(vars().expand("${version}${"), "1.0.0${");
+    }
+
+    #[test]
+    fn expands_several_placeholders_in_one_argument() {
+        assert_eq!(
+            vars().expand("${game_dir}/${version}:${classpath}"),
+            "/games/test/1.0.0:a.jar:b.jar"
+        );
+    }
+
+    #[test]
+    fn adds_the_classpath_once() {
+        let args = build_args("a.jar:b.jar", &config(&[]), &profile(&[]), &vars());
+        assert_eq!(args, ["-cp", "a.jar:b.jar", "com.example.Main"]);
+
+        let args = build_args("a.jar:b.jar", &config(&["-cp", "${classpath}"]), &profile(&[]), &vars());
+        assert_eq!(args, ["-cp", "a.jar:b.jar", "com.example.Main"]);
+
+        let args = build_args("a.jar:b.jar", &config(&[]), &profile(&["-Djava.class.path=${classpath}"]), &vars());
+        assert_eq!(args, ["-Djava.class.path=a.jar:b.jar", "com.example.Main"]);
+    }
 }
@@ -0,0 +1,236 @@
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+
+use crate::util::{self, Error};
+
+/// Amount of log files kept per profile, older ones are removed on launch.
+const MAX_LOG_FILES: usize = 10;
+
+/// Amount of most recent lines kept in memory for crash reports.
+const TAIL_LINES: usize = 500;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogStream {
+    Stdout,
+    Stderr,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+    Fatal,
+}
+
+/// Level names as they appear in log lines, more severe first.
+const LEVELS: [(&str, LogLevel); 7] = [
+    ("FATAL", LogLevel::Fatal),
+    ("ERROR", LogLevel::Error),
+    ("SEVERE", LogLevel::Error),
+    ("WARN", LogLevel::Warn),
+    ("INFO", LogLevel::Info),
+    ("DEBUG", LogLevel::Debug),
+    ("TRACE", LogLevel::Trace),
+];
+
+impl LogLevel {
+    /// Guesses the level of a line using common log formats, falling back to the stream.
+    ///
+    /// Only `[INFO]`, `[main/INFO]` and a level in front of the message count, so messages that
+    /// merely mention a level keep the level of their stream.
+    pub fn detect(line: &str, stream: LogStream) -> LogLevel {
+        let upper = line.to_uppercase();
+        for (name, level) in LEVELS {
+            if upper.contains(&format!("[{}]", name)) || upper.contains(&format!("/{}]", name)) {
+                return level;
+            }
+        }
+        if let Some(level) = Self::leading(&upper) {
+            return level;
+        }
+
+        if line.starts_with("Exception in thread") || line.starts_with("\tat ") {
+            return LogLevel::Error;
+        }
+
+        match stream {
+            LogStream::Stdout => LogLevel::Info,
+            LogStream::Stderr => LogLevel::Error,
+        }
+    }
+
+    /// Level of lines like `INFO: message` or `12:00:00.123 [main] INFO Class - message`, skipping
+    /// timestamps and bracketed fields in front of it.
+    fn leading(upper: &str) -> Option<LogLevel> {
+        let mut rest = upper.trim_start();
+        loop {
+            if rest.starts_with('[') {
+                rest = rest[rest.find(']')? + 1..].trim_start();
+                continue;
+            }
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            let token = &rest[..end];
+            if token.starts_with(|it: char| it.is_ascii_digit()) {
+                rest = rest[end..].trim_start();
+                continue;
+            }
+            let name = token.trim_end_matches(':');
+            return LEVELS.iter().find(|it| it.0 == name).map(|it| it.1);
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GameLogLine {
+    /// Id of the profile.
+    pub(crate) profile: String,
+    pub(crate) stream: LogStream,
+    pub(crate) level: LogLevel,
+    pub(crate) line: String,
+}
+
+/// Log file of a single game launch.
+pub struct GameLog {
+    profile: String,
+    file: Mutex<File>,
+    tail: Mutex<VecDeque<String>>,
+}
+
+impl GameLog {
+    /// Creates `logs/<profile id>/<timestamp>.log` and removes the oldest logs of the profile.
+    pub fn create(profile: &str) -> Result<GameLog, Error> {
+        let dir = util::get_data_dir()
+            .join("logs")
+            .join(util::sanitize_file_name(profile));
+        std::fs::create_dir_all(&dir)?;
+        rotate(&dir)?;
+
+        let timestamp = chrono::Local::now().format("%Y-%m-%d_%H-%M-%S");
+        let path = dir.join(format!("{}.log", timestamp));
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        println!("Writing game log to: {}", path.to_string_lossy());
+
+        Ok(GameLog {
+            profile: profile.to_string(),
+            file: Mutex::new(file),
+            tail: Mutex::new(VecDeque::with_capacity(TAIL_LINES)),
+        })
+    }
+
+    /// The last lines the game printed, oldest first.
+    pub fn tail(&self) -> Vec<String> {
+        match self.tail.lock() {
+            Ok(tail) => tail.iter().cloned().collect(),
+            Err(_) => vec![],
+        }
+    }
+
+    /// Writes the line to the log file and sends it to the frontend as a `gameLog` event.
+    pub fn log(&self, app: &AppHandle, stream: LogStream, line: String) {
+        let level = LogLevel::detect(&line, stream);
+
+        let time = chrono::Local::now().format("%H:%M:%S");
+        let formatted = format!("[{}] [{:?}] {}", time, stream, line);
+        if let Ok(mut file) = self.file.lock() {
+            if let Err(e) = writeln!(file, "{}", formatted) {
+                println!("Failed to write game log: {:?}", e);
+            }
+        }
+        if let Ok(mut tail) = self.tail.lock() {
+            if tail.len() >= TAIL_LINES {
+                tail.pop_front();
+            }
+            tail.push_back(formatted);
+        }
+
+        let event = GameLogLine {
+            profile: self.profile.to_string(),
+            stream,
+            level,
+            line,
+        };
+        if let Err(e) = app.emit_all("gameLog", event) {
+            println!("Failed to emit game log event: {:?}", e);
+        }
+    }
+}
+
+/// Keeps at most `MAX_LOG_FILES - 1` existing logs so the new one fits in the limit.
+fn rotate(dir: &Path) -> Result<(), Error> {
+    let mut logs = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().map(|it| it == "log").unwrap_or(false) {
+            logs.push(path);
+        }
+    }
+
+    // Timestamps in the names sort chronologically.
+    logs.sort();
+    while logs.len() >= MAX_LOG_FILES {
+        let oldest = logs.remove(0);
+        println!("Removing old game log: {}", oldest.to_string_lossy());
+        std::fs::remove_file(oldest)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detect(line: &str) -> LogLevel {
+        LogLevel::detect(line, LogStream::Stdout)
+    }
+
+    #[test]
+    fn detects_bracketed_levels() {
+        assert_eq!(detect("[12:00:00] [main/INFO]: Loading"), LogLevel::Info);
+        assert_eq!(detect("[12:00:00] [Render thread/WARN]: Slow frame"), LogLevel::Warn);
+        assert_eq!(detect("[ERROR] Failed to load texture"), LogLevel::Error);
+        assert_eq!(detect("[debug] lowercase works too"), LogLevel::Debug);
+    }
+
+    #[test]
+    fn detects_leading_levels() {
+        assert_eq!(detect("ERROR: something broke"), LogLevel::Error);
+        assert_eq!(detect("SEVERE: something broke"), LogLevel::Error);
+        assert_eq!(detect("12:00:00.123 [main] DEBUG com.example.Game - Tick"), LogLevel::Debug);
+        assert_eq!(detect("2024-01-01 12:00:00 TRACE entering"), LogLevel::Trace);
+        assert_eq!(detect("FATAL out of everything"), LogLevel::Fatal);
+    }
+
+    #[test]
+    fn ignores_levels_inside_messages() {
+        assert_eq!(detect("[12:00:00] [main/INFO]: Registered ERROR handler"), LogLevel::Info);
+        assert_eq!(detect("Player wrote ERROR in chat"), LogLevel::Info);
+        assert_eq!(LogLevel::detect("Showing INFO panel", LogStream::Stderr), LogLevel::Error);
+        assert_eq!(detect("12:00:00 [main] Loaded 3 WARN icons"), LogLevel::Info);
+    }
+
+    #[test]
+    fn detects_stack_traces() {
+        assert_eq!(detect("Exception in thread \"main\" java.lang.NullPointerException"), LogLevel::Error);
+        assert_eq!(detect("\tat com.example.Game.main(Game.java:10)"), LogLevel::Error);
+    }
+
+    #[test]
+    fn falls_back_to_the_stream() {
+        assert_eq!(LogLevel::detect("plain output", LogStream::Stdout), LogLevel::Info);
+        assert_eq!(LogLevel::detect("plain output", LogStream::Stderr), LogLevel::Error);
+        assert_eq!(detect(""), LogLevel::Info);
+        assert_eq!(detect("[unterminated"), LogLevel::Info);
+    }
+}
@@ -3,18 +3,23 @@
 
 extern crate msgbox;
 
-use std::fs::{File, OpenOptions};
-use std::path::Path;
+use std::path::PathBuf;
 use std::process::exit;
 
-use serde_json::from_reader;
+use semver::Version;
 use tauri::{AppHandle, generate_handler, State, Window};
 use tauri::api::dialog::blocking::FileDialogBuilder;
+use uuid::Uuid;
 
 use profiles::Profiles;
 
-use crate::profiles::Profile;
-use crate::sdk::{SDKInfo, SDKList};
+use crate::crash::CrashReportInfo;
+use crate::installs::{InstalledSdk, SdkVerification};
+use crate::jdks::{LocalJdk, LocalJdks};
+use crate::package::PackageReport;
+use crate::profiles::{LaunchSettings, Profile, SavedLaunchSettings, SdkOverride};
+use crate::running::{GameExit, RunningGameInfo, RunningGames};
+use crate::settings::LauncherSettings;
 use crate::util::Error;
 
 mod util;
@@ -25,6 +30,15 @@ mod commands;
 mod profiles;
 mod net;
 mod game;
+mod logs;
+mod crash;
+mod running;
+mod settings;
+mod jdks;
+mod installs;
+mod store;
+mod instances;
+mod package;
 
 #[macro_export]
 macro_rules! show_error {
@@ -44,56 +58,107 @@ fn close() {
 async fn launch(
     app: AppHandle,
     window: Window,
-    profile: Profile,
-) -> Result<i32, Error> {
-    let client = net::build_client()?;
+    running_state: State<'_, RunningGames>,
+    local_jdks: State<'_, LocalJdks>,
+    profile_state: State<'_, Profiles>,
+    id: Uuid,
+) -> Result<RunningGameInfo, Error> {
+    // The stored profile, the UI's copy may be outdated.
+    let profile = profiles::find(&profile_state.loaded()?, id)?.clone();
+    profile.validate()?;
+
+    // Held until the game is registered, so a second launch of the profile fails right away.
+    let reservation = running_state.reserve(&app, profile.id)?;
 
-    let sdk_list: SDKList = sdk::fetch_sdk(client.to_owned())
-        .await
-        .map_err(|e| Error::Fetch(format!("Failed to fetch SDK: {:?}", e)))?;
+    let client = net::build_client()?;
+    let settings = settings::load()?;
 
-    let version_dir =
-        "games/".to_string() + "/" + &profile.game + "/versions/" + &profile.version + "/";
+    let version_dir = profile.version_dir();
     let cfg = profiles::read_cfg(&version_dir)
         .map_err(|e| Error::Launch(format!("Failed to read version config: {:?}", e)))?;
-    let _meta = profiles::read_meta(&version_dir)
+    let meta = profiles::read_meta(&version_dir)
         .map_err(|e| Error::Launch(format!("Failed to read version metadata, {:?}", e)))?;
-    let sdk_info_map = sdk_list
-        .0
-        .get(&cfg.sdk.r#type)
-        .ok_or_else(|| Error::Launch(format!("Unknown SDK type: {}", &cfg.sdk.r#type)))?;
-
-    let mut sdk_info: Option<&SDKInfo> = None;
-    let vv = util::get_version_req(&cfg)?;
-    let versions = &vv;
-
-    println!("Version range: {}", versions);
-    for ele in sdk_info_map {
-        let v = &ele.1.version;
-        let is_newer_supported = sdk_info.is_some()
-            && versions.matches(v)
-            && sdk_info.unwrap().version < *v;
-        let is_supported = sdk_info.is_none() && versions.matches(v);
-        if (is_supported) || (is_newer_supported) {
-            sdk_info = Some(ele.1);
-        }
-    }
-    let sdk_info = sdk_info.ok_or_else(|| {
-        Error::Launch(format!("No compatible versions found: {}", &cfg.sdk.r#type))
-    })?;
-    sdk::retrieve_sdk(app, client, sdk_info, &cfg, &_meta)
-        .await
-        .map_err(Error::Launch)?;
 
-    let game: String = profile.game;
-    let version: String = profile.version;
+    let game_versions = util::get_version_req(&cfg)?;
+    println!("Version range: {}", game_versions);
+
+    // Probing runs the JDK, so it happens once and off the async runtime.
+    let sdk_override = &profile.settings.sdk_override;
+    let override_jdk = match sdk_override.to_owned() {
+        Some(sdk_override) => {
+            let sdk_type = cfg.sdk.r#type.to_owned();
+            let game_versions = game_versions.to_owned();
+            let (jdk, warnings) = tauri::async_runtime::spawn_blocking(move || {
+                let jdk = sdk_override.probe(&sdk_type)?;
+                let warnings = sdk_override.warnings(&sdk_type, &game_versions, jdk.as_ref());
+                Ok::<_, Error>((jdk, warnings))
+            })
+            .await
+            .map_err(|e| Error::Launch(format!("Failed to check the profile's SDK: {}", e)))??;
+            for warning in warnings {
+                println!("Warning: {}", warning);
+            }
+            jdk
+        }
+        None => None,
+    };
+    let versions = match sdk_override.as_ref().and_then(|it| it.version_req()) {
+        Some(versions) => {
+            println!("Using the profile's SDK range: {}", versions);
+            versions
+        }
+        None => game_versions,
+    };
 
-    let version_dir = "games/".to_string() + "/" + &game + "/versions/" + &version + "/";
+    let discovered = if jdks::supports_type(&cfg.sdk.r#type) {
+        local_jdks.get(false).await?
+    } else {
+        vec![]
+    };
+    let local_jdk = || jdks::find_compatible(&discovered, &cfg.sdk.r#type, &versions);
+    let preferred = match override_jdk {
+        Some(jdk) => Some(jdk.resolve()),
+        None if settings.prefer_local_jdks => local_jdk(),
+        None => None,
+    };
 
-    let cfg = profiles::read_cfg(&version_dir)
-        .map_err(|e| Error::Launch(format!("Failed to read version config: {:?}", e)))?;
-    let meta = profiles::read_meta(&version_dir)
-        .map_err(|e| Error::Launch(format!("Failed to read version metadata, {:?}", e)))?;
+    let sdk = match preferred {
+        Some(sdk) => sdk,
+        None => match sdk::fetch_sdk(client.to_owned(), &settings).await {
+            Ok(sdk_list) => {
+                let sdk_info = sdk::select_sdk(&sdk_list, &cfg.sdk.r#type, &versions)?;
+                match sdk::retrieve_sdk(app.to_owned(), client, sdk_info, &cfg, &meta, &settings).await {
+                    Ok(installed) => {
+                        let sdk = sdk_info.resolve(&cfg.sdk.r#type)?;
+                        if installed {
+                            if let Err(e) = launch::check_runnable(&sdk) {
+                                // Otherwise the broken SDK would be picked up as installed next time
+                                if let Err(e) = installs::remove(&cfg.sdk.r#type, &sdk_info.version) {
+                                    println!("Failed to remove broken SDK {}: {:?}", sdk_info.version, e);
+                                }
+                                return Err(e);
+                            }
+                        }
+                        sdk
+                    }
+                    Err(e) => {
+                        println!("Failed to retrieve SDK {}, looking for installed SDKs: {:?}", sdk_info.version, e);
+                        sdk::find_installed(&cfg.sdk.r#type, &versions)
+                            .or_else(local_jdk)
+                            .ok_or(e)?
+                    }
+                }
+            }
+            Err(e) => {
+                println!("No SDK index available, looking for installed SDKs: {:?}", e);
+                sdk::find_installed(&cfg.sdk.r#type, &versions)
+                    .or_else(local_jdk)
+                    .ok_or_else(|| {
+                        Error::Fetch(format!("Failed to fetch SDK and no compatible SDK is installed: {:?}", e))
+                    })?
+            }
+        },
+    };
 
     let binding = util::get_data_dir();
     let data_dir_raw = binding.to_str().unwrap();
@@ -104,100 +169,250 @@ async fn launch(
 
     let cp = util::get_classpath(&cfg, meta, data_dir);
 
-    window.hide().expect("Failed to hide window.");
+    let info = launch::run_with_sdk(&app, &window, &sdk, &cfg, &profile, data_dir, cp)?;
+    reservation.complete();
+    Ok(info)
+}
 
-    let code = match launch::run_with_sdk(&window, sdk_info, &cfg, data_dir, cp) {
-        Ok(value) => value,
-        Err(value) => return value,
-    };
+#[tauri::command(async)]
+fn list_running(running_state: State<'_, RunningGames>) -> Result<Vec<RunningGameInfo>, Error> {
+    running_state.list()
+}
 
-    window.show().expect("Failed to show window again.");
-    Err(Error::Launch(format!("Game crashed, exit code: {}", code)))
+#[tauri::command(async)]
+fn kill_game(running_state: State<'_, RunningGames>, id: Uuid) -> Result<(), Error> {
+    running_state.kill(id)
+}
+
+#[tauri::command]
+async fn wait_game(
+    running_state: State<'_, RunningGames>,
+    id: Uuid,
+) -> Result<GameExit, Error> {
+    running_state.wait(id).await
 }
 
 #[tauri::command(async)]
 fn load_profiles(profile_state: State<'_, Profiles>) -> Result<Vec<Profile>, Error> {
     println!("Loading profiles.");
-    let mutex_profiles = &mut profile_state.inner().0.lock()?;
-    if !mutex_profiles.is_empty() {
-        let mut profiles = vec![];
-        for profile in mutex_profiles.iter() {
-            profiles.push(profile.clone())
-        }
-        println!("Reusing old state.");
-        return Ok(profiles);
+    let profiles = profile_state.loaded()?;
+    println!("Returning profile data.");
+    Ok(profiles.clone())
+}
+
+/// Asks for a game package and imports it, `None` when the dialog is cancelled.
+#[tauri::command(async)]
+fn import(profile_state: State<'_, Profiles>, name: String) -> Result<Option<Profile>, Error> {
+    let path = match FileDialogBuilder::new().pick_file() {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+    import_path(profile_state, path, name).map(Some)
+}
+
+/// Lists every problem of a game package, without importing it.
+#[tauri::command(async)]
+fn validate_package(path: PathBuf) -> Result<PackageReport, Error> {
+    package::validate_file(&path, &util::get_data_dir())
+}
+
+#[tauri::command(async)]
+fn import_path(profile_state: State<'_, Profiles>, path: PathBuf, name: String) -> Result<Profile, Error> {
+    // Extracting takes a while, so the profiles are only locked to add the new one.
+    let data_dir = util::get_data_dir();
+    let profile = profiles::extract(&path, &name, &data_dir)?;
+    let mut profiles = profile_state.loaded()?;
+    profiles::add(&mut profiles, profile.clone(), &data_dir)?;
+    Ok(profile)
+}
+
+#[tauri::command(async)]
+fn update_profile(
+    profile_state: State<'_, Profiles>,
+    profile: Profile,
+) -> Result<Profile, Error> {
+    profile.validate()?;
+    let mut profiles = profile_state.loaded()?;
+    let mut updated = profiles.clone();
+    let existing = profiles::find_mut(&mut updated, profile.id)?;
+    if existing.game != profile.game || existing.version != profile.version {
+        profiles::read_cfg(&profile.version_dir()).map_err(|e| {
+            Error::msg(&format!("Game version {} {} is not installed: {:?}", profile.game, profile.version, e))
+        })?;
     }
+    *existing = profile.clone();
+
+    store::save(&updated)?;
+    *profiles = updated;
+    Ok(profile)
+}
 
-    let binding = util::get_data_dir().join("profiles.json");
-    let path = binding.as_path();
-    if !Path::exists(path) {
-        println!("Profiles data doesn't exist, returning empty vec.");
-        return Ok(vec![]);
+/// Removes a profile, its instance directory is kept as it may contain saves.
+#[tauri::command(async)]
+fn delete_profile(
+    profile_state: State<'_, Profiles>,
+    running_state: State<'_, RunningGames>,
+    id: Uuid,
+) -> Result<(), Error> {
+    if running_state.is_running(id)? {
+        return Err(Error::msg(&format!("Can't delete a running profile: {}", id)));
     }
+    let mut profiles = profile_state.loaded()?;
+    profiles::find(&profiles, id)?;
+    let mut updated = profiles.clone();
+    updated.retain(|profile| profile.id != id);
+
+    store::save(&updated)?;
+    *profiles = updated;
+    Ok(())
+}
 
-    let open = OpenOptions::new().read(true).open(path)?;
+/// Copies a profile with a new id, placed right after the original.
+#[tauri::command(async)]
+fn duplicate_profile(
+    profile_state: State<'_, Profiles>,
+    id: Uuid,
+    name: Option<String>,
+) -> Result<Profile, Error> {
+    let mut profiles = profile_state.loaded()?;
+    let index = profiles
+        .iter()
+        .position(|profile| profile.id == id)
+        .ok_or_else(|| Error::msg(&format!("Unknown profile: {}", id)))?;
+    let mut profile = profiles[index].clone();
+    profile.id = Uuid::new_v4();
+    profile.name = name.unwrap_or_else(|| format!("{} (Copy)", profile.name));
+    profile.validate()?;
+    let mut updated = profiles.clone();
+    updated.insert(index + 1, profile.clone());
+
+    store::save(&updated)?;
+    *profiles = updated;
+    Ok(profile)
+}
 
-    let mut profiles: Vec<Profile> = from_reader(open)?;
-    mutex_profiles.append(&mut profiles);
-    println!("Returning profile data.");
-    Ok(profiles)
+#[tauri::command(async)]
+fn get_launch_settings(
+    profile_state: State<'_, Profiles>,
+    id: Uuid,
+) -> Result<LaunchSettings, Error> {
+    let profiles = profile_state.loaded()?;
+    Ok(profiles::find(&profiles, id)?.settings.clone())
 }
 
 #[tauri::command(async)]
-fn import(profile_state: State<'_, Profiles>, name: String) -> Result<Profile, Error> {
-    let path_buf = FileDialogBuilder::new().pick_file();
-    if path_buf.is_none() {
-        return Ok(Profile {
-            game: "error".to_string(),
-            name: "ERROR".to_string(),
-            version: "error".to_string(),
-        });
+fn set_launch_settings(
+    profile_state: State<'_, Profiles>,
+    id: Uuid,
+    settings: LaunchSettings,
+) -> Result<SavedLaunchSettings, Error> {
+    settings.validate()?;
+
+    // Probing an SDK by path takes a while, so the profiles aren't locked during the check.
+    let profile = profiles::find(&profile_state.loaded()?, id)?.clone();
+    let warnings = match &settings.sdk_override {
+        Some(sdk_override) => profile.check_sdk_override(sdk_override)?,
+        None => vec![],
     };
 
-    let path = &path_buf
-        .unwrap()
-        .to_str()
-        .unwrap()
-        .to_string()
-        .as_mut()
-        .to_owned();
-
-    let file = File::open(path)?;
-    #[allow(unused_qualifications)]
-        let profile = crate::profiles::list_zip_contents(&file, &name)?;
-    drop(file);
-
-    let mut profile_mutex = profile_state.inner().0.try_lock()?;
-    profile_mutex.push(profile.clone());
-
-    let path = &Path::new(&util::get_data_dir())
-        .to_path_buf()
-        .join("profiles.json");
-    let mut options = &mut OpenOptions::new();
-    if !Path::exists(path) {
-        options = options.create_new(true);
-    }
+    let mut profiles = profile_state.loaded()?;
+    let mut updated = profiles.clone();
+    let profile = profiles::find_mut(&mut updated, id)?;
+    profile.settings = settings;
+    let profile = profile.clone();
 
-    let open = options.write(true).open(path)?;
+    store::save(&updated)?;
+    *profiles = updated;
+    Ok(SavedLaunchSettings { profile, warnings })
+}
 
-    let mut profiles = vec![];
-    let binding = profile_mutex;
-    for profile in binding.iter() {
-        profiles.push(profile)
-    }
-    serde_json::to_writer(open, &profiles)?;
+/// Warnings for the UI about a profile's SDK override, errors when it can't work at all.
+#[tauri::command(async)]
+fn check_sdk_override(
+    profile_state: State<'_, Profiles>,
+    id: Uuid,
+    sdk_override: SdkOverride,
+) -> Result<Vec<String>, Error> {
+    let profile = profiles::find(&profile_state.loaded()?, id)?.clone();
+    profile.check_sdk_override(&sdk_override)
+}
 
-    Ok(profile)
+#[tauri::command(async)]
+fn list_crash_reports(id: Option<Uuid>) -> Result<Vec<CrashReportInfo>, Error> {
+    crash::list_reports(id.map(|it| it.to_string()).as_deref())
+}
+
+/// Probes the local JDKs again, so JDKs installed while the launcher runs show up.
+#[tauri::command]
+async fn list_local_jdks(local_jdks: State<'_, LocalJdks>) -> Result<Vec<LocalJdk>, Error> {
+    local_jdks.get(true).await
+}
+
+#[tauri::command(async)]
+fn list_sdks(profile_state: State<'_, Profiles>) -> Result<Vec<InstalledSdk>, Error> {
+    let profiles = profile_state.loaded()?;
+    installs::list(&profiles)
+}
+
+#[tauri::command(async)]
+fn verify_sdk(sdk_type: String, version: Version) -> Result<SdkVerification, Error> {
+    installs::verify(&sdk_type, &version)
+}
+
+#[tauri::command(async)]
+fn remove_sdk(sdk_type: String, version: Version) -> Result<(), Error> {
+    installs::remove(&sdk_type, &version)
+}
+
+#[tauri::command(async)]
+fn gc_sdks(profile_state: State<'_, Profiles>) -> Result<Vec<InstalledSdk>, Error> {
+    let profiles = profile_state.loaded()?;
+    installs::gc(&profiles)
+}
+
+#[tauri::command(async)]
+fn get_settings() -> Result<LauncherSettings, Error> {
+    settings::load()
+}
+
+#[tauri::command(async)]
+fn set_settings(settings: LauncherSettings) -> Result<(), Error> {
+    settings::save(&settings)
 }
 
 fn main() {
+    if let Err(e) = sdk::clean_staging() {
+        println!("Failed to clean up interrupted SDK installs: {:?}", e);
+    }
+
     let run = tauri::Builder::default()
         .manage(Profiles(Default::default()))
+        .manage(RunningGames::default())
+        .manage(LocalJdks::default())
         .invoke_handler(generate_handler![
             close,
             launch,
             import,
-            load_profiles
+            import_path,
+            validate_package,
+            load_profiles,
+            update_profile,
+            delete_profile,
+            duplicate_profile,
+            get_launch_settings,
+            set_launch_settings,
+            check_sdk_override,
+            list_running,
+            kill_game,
+            wait_game,
+            list_crash_reports,
+            list_local_jdks,
+            list_sdks,
+            verify_sdk,
+            remove_sdk,
+            gc_sdks,
+            get_settings,
+            set_settings
         ])
         .run(tauri::generate_context!());
     if run.is_err() {
@@ -2,9 +2,13 @@ use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use tauri::{AppHandle, Manager, Runtime};
 use std::path::{Path, PathBuf};
-use std::fs::File;
-use std::io::Write;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
 use futures_util::StreamExt;
+use reqwest::header::{CONTENT_RANGE, RANGE};
+use reqwest::{StatusCode, Url};
+use sha2::{Digest, Sha256};
 use crate::util::Error;
 
 #[derive(Deserialize, Serialize, Clone)]
@@ -14,6 +18,103 @@ pub struct DownloadInfo {
     pub(crate) percent: u32,
     pub(crate) downloading: bool,
     pub(crate) status: String,
+    pub(crate) phase: DownloadPhase,
+}
+
+/// Step of an SDK install a `downloadProgress` event belongs to.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DownloadPhase {
+    Downloading,
+    Verifying,
+    Extracting,
+    Finalising,
+}
+
+/// Minimum time between two progress events of the same emitter.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Sends `downloadProgress` events at a fixed maximum rate, so large archives don't flood the
+/// webview with IPC messages.
+pub struct ProgressEmitter<R: Runtime> {
+    app: AppHandle<R>,
+    phase: DownloadPhase,
+    status: String,
+    last_emit: Option<Instant>,
+}
+
+impl<R: Runtime> ProgressEmitter<R> {
+    pub fn new(app: &AppHandle<R>, phase: DownloadPhase, status: String) -> ProgressEmitter<R> {
+        ProgressEmitter {
+            app: app.to_owned(),
+            phase,
+            status,
+            last_emit: None,
+        }
+    }
+
+    /// Reports progress, dropping the event if the previous one was sent too recently.
+    pub fn progress(&mut self, done: u64, total: u64) {
+        if let Some(last_emit) = self.last_emit {
+            if last_emit.elapsed() < PROGRESS_INTERVAL {
+                return;
+            }
+        }
+        self.emit(done, total, true);
+    }
+
+    /// Reports progress regardless of the rate limit, e.g. at the start or end of a phase.
+    pub fn emit(&mut self, done: u64, total: u64, downloading: bool) {
+        self.last_emit = Some(Instant::now());
+        let info = DownloadInfo {
+            downloaded: done,
+            total,
+            percent: if total > 0 { (100 * done.min(total) / total) as u32 } else { 0 },
+            downloading,
+            status: self.status.to_string(),
+            phase: self.phase,
+        };
+        if let Err(e) = self.app.emit_all("downloadProgress", info) {
+            println!("Failed to emit progress event: {:?}", e);
+        }
+    }
+
+    /// Sends the final event of the install, hiding the progress in the UI.
+    pub fn complete(&mut self) {
+        self.status = "Completed!".to_string();
+        self.emit(1, 1, false);
+    }
+}
+
+/// Expected hash and size of a downloaded file, each check is skipped when unknown.
+#[derive(Default, Clone, Debug)]
+pub struct ExpectedDownload {
+    pub(crate) sha256: Option<String>,
+    pub(crate) size: Option<u64>,
+}
+
+impl ExpectedDownload {
+    fn verify(&self, file_name: &str, sha256: &str, size: u64) -> Result<(), Error> {
+        if let Some(expected) = self.size {
+            if expected != size {
+                return Err(Error::ChecksumMismatch {
+                    file: file_name.to_string(),
+                    expected: format!("{} bytes", expected),
+                    actual: format!("{} bytes", size),
+                });
+            }
+        }
+        if let Some(expected) = &self.sha256 {
+            if !expected.eq_ignore_ascii_case(sha256) {
+                return Err(Error::ChecksumMismatch {
+                    file: file_name.to_string(),
+                    expected: expected.to_lowercase(),
+                    actual: sha256.to_string(),
+                });
+            }
+        }
+        Ok(())
+    }
 }
 
 pub fn build_client() -> Result<Client, Error> {
@@ -23,49 +124,456 @@ pub fn build_client() -> Result<Client, Error> {
     Ok(client)
 }
 
+/// How often and how fast failed downloads are retried.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    pub(crate) max_retries: u32,
+    pub(crate) initial_backoff: Duration,
+    pub(crate) max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 5,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Exponential backoff before the given retry, starting at 1.
+    fn backoff(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry.saturating_sub(1));
+        self.initial_backoff
+            .checked_mul(factor)
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff)
+    }
+}
+
+/// Failure of a single download attempt.
+enum AttemptError {
+    /// Network hiccups and server errors, worth retrying.
+    Transient(Error),
+    /// Errors that won't go away by retrying, like a missing file or a full disk.
+    Fatal(Error),
+}
+
+/// State of the `.part` file shared between download attempts.
+struct PartialDownload {
+    path: PathBuf,
+    file_name: String,
+    downloaded: u64,
+    hasher: Sha256,
+}
+
+impl PartialDownload {
+    /// Opens an existing `.part` file, hashing what was downloaded before.
+    fn open(path: PathBuf, file_name: String) -> Result<PartialDownload, Error> {
+        let mut hasher = Sha256::new();
+        let mut downloaded = 0;
+        if path.exists() {
+            let mut file = File::open(&path)?;
+            downloaded = io::copy(&mut file, &mut hasher)?;
+            println!("Resuming download of {} at {} bytes", file_name, downloaded);
+        }
+
+        Ok(PartialDownload {
+            path,
+            file_name,
+            downloaded,
+            hasher,
+        })
+    }
+
+    fn restart(&mut self) -> Result<(), Error> {
+        File::create(&self.path)?;
+        self.downloaded = 0;
+        self.hasher = Sha256::new();
+        Ok(())
+    }
+}
+
+/// Downloads `url` to `file_path`, keeping partial data in a `.part` file that is resumed with
+/// HTTP range requests when a transient error occurs. Returns the SHA-256 of the file.
 pub async fn download_file<R: Runtime>(
     app: AppHandle<R>,
     client: Client,
     url: String,
     file_path: PathBuf,
-) -> Result<(), Error> {
-    let response = client
-        .get(url)
-        .send()
-        .await
-        .map_err(|e| Error::Download(format!("Failed to make request: {:?}", e)))?;
-
-    let total_size = response.content_length().unwrap_or(0);
-    let mut downloaded_size = 0;
-
-    let mut file = File::create(&file_path)?;
+    expected: &ExpectedDownload,
+    retry: &RetryPolicy,
+) -> Result<String, Error> {
     let file_name = Path::new(&file_path)
         .file_name()
         .ok_or_else(|| Error::Download("Failed to get file path".to_string()))?
         .to_str()
         .unwrap()
         .to_owned();
+    let mut part_path = file_path.clone().into_os_string();
+    part_path.push(".part");
+
+    let mut part = PartialDownload::open(PathBuf::from(part_path), file_name.to_string())?;
+
+    if let Some(source) = local_file(&url) {
+        copy_local_file(&app, &source, &mut part)?;
+    } else {
+        download_with_retries(&app, &client, &url, &mut part, retry).await?;
+    }
+
+    ProgressEmitter::new(&app, DownloadPhase::Verifying, format!("Verifying: {}", file_name))
+        .emit(part.downloaded, part.downloaded, true);
+    let sha256 = format!("{:x}", part.hasher.finalize());
+    if let Err(e) = expected.verify(&file_name, &sha256, part.downloaded) {
+        println!("Removing corrupt download: {}", part.path.to_string_lossy());
+        std::fs::remove_file(&part.path)?;
+        return Err(e);
+    }
+
+    std::fs::rename(&part.path, &file_path)?;
+    Ok(sha256)
+}
+
+async fn download_with_retries<R: Runtime>(
+    app: &AppHandle<R>,
+    client: &Client,
+    url: &str,
+    part: &mut PartialDownload,
+    retry: &RetryPolicy,
+) -> Result<(), Error> {
+    let file_name = part.file_name.to_string();
+    let mut retries = 0;
+    loop {
+        match download_attempt(app, client, url, part, retries, retry.max_retries).await {
+            Ok(()) => return Ok(()),
+            Err(AttemptError::Fatal(e)) => return Err(e),
+            Err(AttemptError::Transient(e)) => {
+                if retries >= retry.max_retries {
+                    return Err(Error::Download(format!(
+                        "Giving up on {} after {} retries: {}",
+                        file_name, retries, e
+                    )));
+                }
+                retries += 1;
+                let backoff = retry.backoff(retries);
+                println!(
+                    "Download of {} failed ({}), retry {}/{} in {:?}",
+                    file_name, e, retries, retry.max_retries, backoff
+                );
+                tokio::time::sleep(backoff).await;
+            }
+        }
+    }
+}
+
+/// Path of a `file://` URL, used for mirrors on air-gapped machines.
+pub fn local_file(url: &str) -> Option<PathBuf> {
+    let url = Url::parse(url).ok()?;
+    if url.scheme() != "file" {
+        return None;
+    }
+    url.to_file_path().ok()
+}
+
+fn copy_local_file<R: Runtime>(app: &AppHandle<R>, source: &Path, part: &mut PartialDownload) -> Result<(), Error> {
+    part.restart()?;
+    let mut input = File::open(source)
+        .map_err(|e| Error::Download(format!("Failed to open {}: {:?}", source.to_string_lossy(), e)))?;
+    let total_size = input.metadata()?.len();
+    let mut output = OpenOptions::new().append(true).open(&part.path)?;
+
+    let mut progress = ProgressEmitter::new(app, DownloadPhase::Downloading, format!("Copying: {}", part.file_name));
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let read = input.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        output.write_all(&buf[..read])?;
+        part.hasher.update(&buf[..read]);
+        part.downloaded += read as u64;
+        progress.progress(part.downloaded, total_size);
+    }
+    progress.emit(part.downloaded, total_size, true);
+    Ok(())
+}
+
+/// Fetches a small file like an index, `file://` URLs are read from disk.
+pub async fn fetch_bytes(client: &Client, url: &str) -> Result<Vec<u8>, Error> {
+    if let Some(path) = local_file(url) {
+        return Ok(std::fs::read(path)?);
+    }
+    let bytes = client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+    Ok(bytes.to_vec())
+}
+
+/// Status shown while downloading, with the retry count once an attempt failed.
+fn attempt_status(file_name: &str, retries: u32, max_retries: u32) -> String {
+    if retries > 0 {
+        format!("Downloading: {} (retry {}/{})", file_name, retries, max_retries)
+    } else {
+        format!("Downloading: {}", file_name)
+    }
+}
+
+async fn download_attempt<R: Runtime>(
+    app: &AppHandle<R>,
+    client: &Client,
+    url: &str,
+    part: &mut PartialDownload,
+    retries: u32,
+    max_retries: u32,
+) -> Result<(), AttemptError> {
+    let mut request = client.get(url);
+    if part.downloaded > 0 {
+        request = request.header(RANGE, format!("bytes={}-", part.downloaded));
+    }
+    let response = request
+        .send()
+        .await
+        .map_err(|e| AttemptError::Transient(Error::Download(format!("Failed to make request: {:?}", e))))?;
 
+    let status = response.status();
+    if status == StatusCode::RANGE_NOT_SATISFIABLE {
+        // Nothing left past our offset, either the part is complete or the file changed.
+        let total = response
+            .headers()
+            .get(CONTENT_RANGE)
+            .and_then(|it| it.to_str().ok())
+            .and_then(|it| it.rsplit_once('/'))
+            .and_then(|it| it.1.parse::<u64>().ok());
+        if total == Some(part.downloaded) {
+            return Ok(());
+        }
+        part.restart().map_err(AttemptError::Fatal)?;
+        return Err(AttemptError::Transient(Error::Download(format!(
+            "Server rejected resume of {}, restarting",
+            part.file_name
+        ))));
+    }
+    if status.is_server_error()
+        || status == StatusCode::REQUEST_TIMEOUT
+        || status == StatusCode::TOO_MANY_REQUESTS
+    {
+        return Err(AttemptError::Transient(Error::Download(format!("Server responded with {}", status))));
+    }
+    if !status.is_success() {
+        return Err(AttemptError::Fatal(Error::Download(format!(
+            "Failed to download {}: server responded with {}",
+            part.file_name, status
+        ))));
+    }
+
+    // Servers without range support send the whole file again.
+    if part.downloaded > 0 && status != StatusCode::PARTIAL_CONTENT {
+        println!("Server doesn't support resuming {}, restarting", part.file_name);
+        part.restart().map_err(AttemptError::Fatal)?;
+    }
+
+    let total_size = response.content_length().map(|it| it + part.downloaded).unwrap_or(0);
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&part.path)
+        .map_err(|e| AttemptError::Fatal(e.into()))?;
+
+    let status = attempt_status(&part.file_name, retries, max_retries);
+    let mut progress = ProgressEmitter::new(app, DownloadPhase::Downloading, status);
     let mut response = response.bytes_stream();
     while let Some(chunk) = response.next().await {
-        let chunk = chunk.map_err(|e| Error::Download(format!("Failed to read chunk: {:?}", e)))?;
-        downloaded_size += chunk.len() as u64;
+        let chunk = chunk.map_err(|e| AttemptError::Transient(Error::Download(format!("Failed to read chunk: {:?}", e))))?;
         file.write_all(&chunk)
-            .map_err(|e| Error::Download(format!("Failed to write to file: {:?}", e)))?;
-
-        // Emit event
-        app.emit_all(
-            "downloadProgress",
-            DownloadInfo {
-                downloaded: downloaded_size,
-                total: total_size,
-                downloading: true,
-                percent: (100 * downloaded_size / total_size) as u32,
-                status: format!("Downloading: {}", file_name),
-            },
-        )
-            .map_err(|e| Error::Download(format!("Failed to emit event: {:?}", e)))?;
+            .map_err(|e| AttemptError::Fatal(Error::Download(format!("Failed to write to file: {:?}", e))))?;
+        part.downloaded += chunk.len() as u64;
+        part.hasher.update(&chunk);
+        progress.progress(part.downloaded, total_size);
     }
+    progress.emit(part.downloaded, total_size, true);
 
+    if total_size > 0 && part.downloaded < total_size {
+        return Err(AttemptError::Transient(Error::Download(format!(
+            "Connection closed after {} of {} bytes",
+            part.downloaded, total_size
+        ))));
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::util;
+
+    type Requests = Arc<Mutex<Vec<Option<u64>>>>;
+
+    fn data() -> Vec<u8> {
+        (0..100).collect()
+    }
+
+    fn sha256(data: &[u8]) -> String {
+        format!("{:x}", Sha256::digest(data))
+    }
+
+    /// Raw HTTP response, `Content-Length` is given separately so a body can be cut short.
+    fn response(status: &str, headers: &[String], content_length: usize, body: &[u8]) -> Vec<u8> {
+        let mut response = format!("HTTP/1.1 {}\r\nConnection: close\r\nContent-Length: {}\r\n", status, content_length);
+        for header in headers {
+            response.push_str(header);
+            response.push_str("\r\n");
+        }
+        response.push_str("\r\n");
+        let mut response = response.into_bytes();
+        response.extend_from_slice(body);
+        response
+    }
+
+    /// Serves one scripted response per connection, recording the `Range` offset of every request.
+    async fn serve<F>(respond: F) -> (String, Requests)
+    where
+        F: Fn(usize, Option<u64>) -> Vec<u8> + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/file.bin", listener.local_addr().unwrap());
+        let requests = Requests::default();
+        let recorded = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut head = vec![];
+                let mut buf = [0u8; 1024];
+                while !head.ends_with(b"\r\n\r\n") {
+                    match socket.read(&mut buf).await {
+                        Ok(0) | Err(_) => break,
+                        Ok(read) => head.extend_from_slice(&buf[..read]),
+                    }
+                }
+                let head = String::from_utf8_lossy(&head).to_lowercase();
+                let range = head
+                    .lines()
+                    .find_map(|line| line.strip_prefix("range: bytes="))
+                    .and_then(|it| it.trim_end_matches('-').parse().ok());
+                let index = {
+                    let mut recorded = recorded.lock().unwrap();
+                    recorded.push(range);
+                    recorded.len() - 1
+                };
+                let _ = socket.write_all(&respond(index, range)).await;
+                let _ = socket.shutdown().await;
+            }
+        });
+        (url, requests)
+    }
+
+    async fn download(url: &str, dir: &Path, expected: ExpectedDownload, max_retries: u32) -> Result<String, Error> {
+        let app = tauri::test::mock_app();
+        let client = Client::builder().no_proxy().build().unwrap();
+        let retry = RetryPolicy {
+            max_retries,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(1),
+        };
+        download_file(app.handle(), client, url.to_string(), dir.join("file.bin"), &expected, &retry).await
+    }
+
+    #[tokio::test]
+    async fn resumes_after_dropped_connection() {
+        let dir = util::test_dir("net-resume");
+        let (url, requests) = serve(|index, range| match (index, range) {
+            (0, None) => response("200 OK", &[], 100, &data()[..50]),
+            (1, Some(50)) => response("206 Partial Content", &["Content-Range: bytes 50-99/100".to_string()], 50, &data()[50..]),
+            _ => response("500 Internal Server Error", &[], 0, &[]),
+        })
+        .await;
+
+        let expected = ExpectedDownload {
+            sha256: Some(sha256(&data())),
+            size: Some(100),
+        };
+        let sha = download(&url, &dir, expected, 2).await.unwrap();
+
+        assert_eq!(sha, sha256(&data()));
+        assert_eq!(std::fs::read(dir.join("file.bin")).unwrap(), data());
+        assert!(!dir.join("file.bin.part").exists());
+        assert_eq!(*requests.lock().unwrap(), vec![None, Some(50)]);
+    }
+
+    #[tokio::test]
+    async fn restarts_when_range_is_ignored() {
+        let dir = util::test_dir("net-restart");
+        std::fs::write(dir.join("file.bin.part"), b"garbage").unwrap();
+        let (url, requests) = serve(|_, _| response("200 OK", &[], 100, &data())).await;
+
+        let sha = download(&url, &dir, ExpectedDownload::default(), 0).await.unwrap();
+
+        assert_eq!(sha, sha256(&data()));
+        assert_eq!(std::fs::read(dir.join("file.bin")).unwrap(), data());
+        assert_eq!(*requests.lock().unwrap(), vec![Some(7)]);
+    }
+
+    #[tokio::test]
+    async fn complete_part_is_kept_on_416() {
+        let dir = util::test_dir("net-416");
+        std::fs::write(dir.join("file.bin.part"), data()).unwrap();
+        let (url, requests) = serve(|_, _| {
+            response("416 Range Not Satisfiable", &["Content-Range: bytes */100".to_string()], 0, &[])
+        })
+        .await;
+
+        let expected = ExpectedDownload {
+            sha256: Some(sha256(&data())),
+            size: Some(100),
+        };
+        let sha = download(&url, &dir, expected, 0).await.unwrap();
+
+        assert_eq!(sha, sha256(&data()));
+        assert_eq!(std::fs::read(dir.join("file.bin")).unwrap(), data());
+        assert_eq!(*requests.lock().unwrap(), vec![Some(100)]);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_retries() {
+        let dir = util::test_dir("net-retries");
+        let (url, requests) = serve(|_, _| response("503 Service Unavailable", &[], 0, &[])).await;
+
+        let error = download(&url, &dir, ExpectedDownload::default(), 2).await.unwrap_err();
+
+        assert!(matches!(&error, Error::Download(message) if message.contains("after 2 retries")), "{}", error);
+        assert_eq!(requests.lock().unwrap().len(), 3);
+        assert!(!dir.join("file.bin").exists());
+    }
+
+    #[test]
+    fn status_shows_retry_count() {
+        assert_eq!(attempt_status("file.bin", 0, 2), "Downloading: file.bin");
+        assert_eq!(attempt_status("file.bin", 1, 2), "Downloading: file.bin (retry 1/2)");
+        assert_eq!(attempt_status("file.bin", 2, 2), "Downloading: file.bin (retry 2/2)");
+    }
+
+    #[tokio::test]
+    async fn checksum_mismatch_removes_part() {
+        let dir = util::test_dir("net-checksum");
+        let (url, _) = serve(|_, _| response("200 OK", &[], 100, &data())).await;
+
+        let expected = ExpectedDownload {
+            sha256: Some("0".repeat(64)),
+            size: None,
+        };
+        let error = download(&url, &dir, expected, 0).await.unwrap_err();
+
+        assert!(matches!(error, Error::ChecksumMismatch { .. }), "{}", error);
+        assert!(!dir.join("file.bin.part").exists());
+        assert!(!dir.join("file.bin").exists());
+    }
+}
@@ -0,0 +1,235 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::File;
+use std::path::Path;
+
+use semver::Version;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use zip::ZipArchive;
+
+use crate::archive;
+use crate::game::{GameConfig, GameMetadata};
+use crate::jdks;
+use crate::sdk;
+use crate::util::{self, Error};
+
+/// A single problem with a game package.
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PackageProblem {
+    /// Zip entry the problem is about, if any.
+    pub(crate) entry: Option<String>,
+    pub(crate) message: String,
+}
+
+/// Everything wrong with a game package, empty when it can be imported.
+#[derive(Serialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PackageReport {
+    pub(crate) problems: Vec<PackageProblem>,
+}
+
+impl PackageReport {
+    pub fn is_valid(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    fn add(&mut self, entry: Option<&str>, message: String) {
+        self.problems.push(PackageProblem {
+            entry: entry.map(|it| it.to_string()),
+            message,
+        });
+    }
+
+    /// Turns a report with problems into an error carrying all of them.
+    pub fn into_result(self) -> Result<(), Error> {
+        if self.is_valid() {
+            return Ok(());
+        }
+        Err(Error::InvalidPackage(self))
+    }
+}
+
+impl fmt::Display for PackageReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let problems: Vec<String> = self
+            .problems
+            .iter()
+            .map(|it| match &it.entry {
+                Some(entry) => format!("{}: {}", entry, it.message),
+                None => it.message.clone(),
+            })
+            .collect();
+        write!(f, "{}", problems.join("; "))
+    }
+}
+
+/// Checks a game package without extracting anything, `data_dir` is where it would be extracted.
+pub fn validate(zip: &mut ZipArchive<&File>, data_dir: &Path) -> PackageReport {
+    let mut report = PackageReport::default();
+
+    let metadata: Option<GameMetadata> = read_json(zip, "metadata.json", &mut report);
+    let config: Option<GameConfig> = read_json(zip, "config.json", &mut report);
+
+    if let Some(metadata) = &metadata {
+        if let Err(e) = Version::parse(&metadata.version) {
+            report.add(
+                Some("metadata.json"),
+                format!("Version '{}' is not a semantic version: {}", metadata.version, e),
+            );
+        }
+        let jar = metadata.version.to_string() + ".jar";
+        if zip.by_name(&jar).is_err() {
+            report.add(Some(&jar), "Game jar is missing".to_string());
+        }
+    }
+
+    let config = match config {
+        Some(config) => config,
+        None => return report,
+    };
+
+    if config.main_class.trim().is_empty() {
+        report.add(Some("config.json"), "'main-class' is empty".to_string());
+    }
+    if config.game.trim().is_empty() || config.game.starts_with('.') || config.game.contains(['/', '\\']) {
+        report.add(Some("config.json"), format!("Invalid game name: '{}'", config.game));
+    }
+
+    let mut seen = HashSet::new();
+    for entry in config.classpath.iter() {
+        if !seen.insert(entry) {
+            report.add(Some(entry), "Listed more than once in the classpath".to_string());
+            continue;
+        }
+        // Classpath entries are extracted into the data directory.
+        if let Err(e) = archive::safe_join(data_dir, entry) {
+            report.add(Some(entry), e.to_string());
+            continue;
+        }
+        match zip.by_name(entry) {
+            Ok(file) if file.is_dir() => report.add(Some(entry), "Classpath entry is a directory".to_string()),
+            Ok(_) => {}
+            Err(_) => report.add(Some(entry), "Classpath entry is missing from the package".to_string()),
+        }
+    }
+
+    if let Err(e) = util::get_version_req(&config) {
+        report.add(Some("config.json"), format!("Invalid SDK version range: {}", e));
+    }
+    let sdk_type = &config.sdk.r#type;
+    if !jdks::supports_type(sdk_type) && !sdk::known_types().contains(sdk_type) {
+        report.add(Some("config.json"), format!("Unknown SDK type: '{}'", sdk_type));
+    }
+
+    report
+}
+
+fn read_json<T: DeserializeOwned>(zip: &mut ZipArchive<&File>, name: &str, report: &mut PackageReport) -> Option<T> {
+    let file = match zip.by_name(name) {
+        Ok(file) => file,
+        Err(_) => {
+            report.add(Some(name), "Required file is missing".to_string());
+            return None;
+        }
+    };
+    match serde_json::from_reader(file) {
+        Ok(value) => Some(value),
+        Err(e) => {
+            report.add(Some(name), format!("Invalid JSON: {}", e));
+            None
+        }
+    }
+}
+
+/// Validates the package at `path`, for showing problems before importing it.
+pub fn validate_file(path: &Path, data_dir: &Path) -> Result<PackageReport, Error> {
+    let file = File::open(path)?;
+    let mut zip = ZipArchive::new(&file)?;
+    Ok(validate(&mut zip, data_dir))
+}
+
+/// Writes a game package with the given config, `files` are added with dummy contents.
+#[cfg(test)]
+pub fn write_test_package(path: &Path, version: &str, config: serde_json::Value, files: &[&str]) {
+    use std::io::Write;
+    use zip::write::FileOptions;
+
+    let mut zip = zip::ZipWriter::new(File::create(path).unwrap());
+    zip.start_file("metadata.json", FileOptions::default()).unwrap();
+    zip.write_all(serde_json::json!({ "version": version }).to_string().as_bytes()).unwrap();
+    zip.start_file("config.json", FileOptions::default()).unwrap();
+    zip.write_all(config.to_string().as_bytes()).unwrap();
+    for name in files {
+        zip.start_file(*name, FileOptions::default()).unwrap();
+        zip.write_all(b"data").unwrap();
+    }
+    zip.finish().unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profiles;
+
+    fn messages(report: &PackageReport) -> Vec<String> {
+        report.problems.iter().map(|it| it.message.clone()).collect()
+    }
+
+    #[test]
+    fn valid_package_has_no_problems() {
+        let dir = util::test_dir("package-valid");
+        let path = dir.join("game.zip");
+        let config = serde_json::json!({
+            "classpath": ["libs/game.jar"],
+            "sdk": {"version": "17", "type": "java"},
+            "main-class": "com.example.Main",
+            "game": "test",
+        });
+        write_test_package(&path, "1.0.0", config, &["1.0.0.jar", "libs/game.jar"]);
+
+        let report = validate_file(&path, &dir.join("data")).unwrap();
+        assert!(report.is_valid(), "{}", report);
+    }
+
+    #[test]
+    fn every_problem_is_reported_and_nothing_is_extracted() {
+        let dir = util::test_dir("package-invalid");
+        let data_dir = dir.join("data");
+        let path = dir.join("game.zip");
+        let config = serde_json::json!({
+            "classpath": ["libs/game.jar", "../evil.jar"],
+            "sdk": {"version": "17", "type": "not-a-real-sdk"},
+            "main-class": " ",
+            "game": "test",
+        });
+        write_test_package(&path, "first", config, &["libs/game.jar", "../evil.jar"]);
+
+        let report = match profiles::extract(&path, "Main", &data_dir) {
+            Err(Error::InvalidPackage(report)) => report,
+            Err(e) => panic!("Expected an invalid package, got {:?}", e),
+            Ok(_) => panic!("Expected an invalid package to be rejected"),
+        };
+        let entries: Vec<Option<&str>> = report.problems.iter().map(|it| it.entry.as_deref()).collect();
+        assert_eq!(
+            entries,
+            vec![
+                Some("metadata.json"),
+                Some("first.jar"),
+                Some("config.json"),
+                Some("../evil.jar"),
+                Some("config.json"),
+            ],
+            "{:?}",
+            messages(&report)
+        );
+        let messages = messages(&report);
+        assert!(messages[0].contains("not a semantic version"));
+        assert_eq!(messages[1], "Game jar is missing");
+        assert_eq!(messages[2], "'main-class' is empty");
+        assert!(messages[4].contains("not-a-real-sdk"));
+
+        assert!(!data_dir.exists());
+    }
+}
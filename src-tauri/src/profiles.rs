@@ -1,35 +1,290 @@
+use std::collections::BTreeMap;
 use std::fs::File;
 use std::io;
 use std::path::{Path, PathBuf};
-use std::sync::Mutex;
+use std::sync::{Mutex, MutexGuard};
 
+use semver::{Comparator, Op, Version, VersionReq};
 use serde::{Deserialize, Serialize};
 use serde_json::from_reader;
+use uuid::Uuid;
 use zip::ZipArchive;
 
+use crate::archive;
 use crate::game::{GameConfig, GameMetadata};
-use crate::show_error;
-use crate::util::Error;
+use crate::jdks::{self, JdkSource, LocalJdk};
+use crate::sdk;
+use crate::package;
+use crate::store;
+use crate::util::{self, Error};
 
 #[derive(Default)]
 pub struct Profiles(pub Mutex<Vec<Profile>>);
 
+impl Profiles {
+    /// Locks the profiles, loading them from disk first if that didn't happen yet.
+    ///
+    /// Anything that saves the profiles or checks what they use must go through this, as the
+    /// empty state would otherwise drop every stored profile or make every SDK look unused.
+    pub fn loaded(&self) -> Result<MutexGuard<'_, Vec<Profile>>, Error> {
+        let mut profiles = self.0.lock()?;
+        if profiles.is_empty() {
+            profiles.extend(store::load()?);
+        }
+        Ok(profiles)
+    }
+}
+
 #[derive(Deserialize, Serialize, Clone)]
 pub struct Profile {
+    /// Stable identity of the profile, nil for profiles saved before ids existed until they're
+    /// loaded again.
+    #[serde(default)]
+    pub id: Uuid,
     pub game: String,
     pub name: String,
     pub version: String,
+    #[serde(default)]
+    pub settings: LaunchSettings,
 }
 
 impl Profile {
-    #[allow(dead_code)]
-    fn clone(&self) -> Profile {
-        Profile {
-            game: self.game.clone(),
-            name: self.name.clone(),
-            version: self.version.clone(),
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.name.trim().is_empty() {
+            return Err(Error::msg("Profile name can't be empty"));
+        }
+        self.settings.validate()
+    }
+
+    /// Directory of the profile's game version, relative to the data directory.
+    pub fn version_dir(&self) -> String {
+        "games/".to_string() + "/" + &self.game + "/versions/" + &self.version + "/"
+    }
+
+    /// Warnings about an SDK override for this profile's game version, errors when it can't work
+    /// at all.
+    pub fn check_sdk_override(&self, sdk_override: &SdkOverride) -> Result<Vec<String>, Error> {
+        let cfg = read_cfg(&self.version_dir())
+            .map_err(|e| Error::Launch(format!("Failed to read version config: {:?}", e)))?;
+        sdk_override.check(&cfg.sdk.r#type, &util::get_version_req(&cfg)?)
+    }
+}
+
+/// A profile whose launch settings were just saved, with the warnings about its SDK override.
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SavedLaunchSettings {
+    pub(crate) profile: Profile,
+    pub(crate) warnings: Vec<String>,
+}
+
+/// Per-profile options used to build the game's command line.
+#[derive(Deserialize, Serialize, Clone, Default, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LaunchSettings {
+    /// Extra JVM options, e.g. GC flags, passed before the main class.
+    #[serde(default)]
+    pub jvm_args: Vec<String>,
+    /// Initial heap size in MiB (`-Xms`).
+    #[serde(default)]
+    pub min_memory: Option<u32>,
+    /// Maximum heap size in MiB (`-Xmx`).
+    #[serde(default)]
+    pub max_memory: Option<u32>,
+    /// System properties passed as `-Dkey=value`.
+    #[serde(default)]
+    pub system_properties: BTreeMap<String, String>,
+    /// Program arguments passed after the main class.
+    #[serde(default)]
+    pub game_args: Vec<String>,
+    /// SDK to use instead of the highest version matching the game's range.
+    #[serde(default)]
+    pub sdk_override: Option<SdkOverride>,
+    /// Where the game keeps its saves, configs and mods.
+    #[serde(default)]
+    pub game_dir_mode: GameDirMode,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum GameDirMode {
+    /// `games/<game>`, shared by every profile of the game.
+    #[default]
+    Shared,
+    /// `instances/<profile id>`, only used by this profile.
+    Isolated,
+}
+
+/// Pins the SDK of a profile, so new SDK releases don't change a working setup.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase", tag = "kind", content = "value")]
+pub enum SdkOverride {
+    /// Exactly this SDK version.
+    Version(Version),
+    /// The highest SDK version in this range.
+    Range(VersionReq),
+    /// The SDK at this path, e.g. a local JDK.
+    Path(PathBuf),
+}
+
+impl SdkOverride {
+    /// Version range used to select the SDK, `None` for SDKs given by path.
+    pub fn version_req(&self) -> Option<VersionReq> {
+        match self {
+            SdkOverride::Version(version) => Some(VersionReq {
+                comparators: vec![Comparator {
+                    op: Op::Exact,
+                    major: version.major,
+                    minor: Some(version.minor),
+                    patch: Some(version.patch),
+                    pre: version.pre.clone(),
+                }],
+            }),
+            SdkOverride::Range(range) => Some(range.clone()),
+            SdkOverride::Path(_) => None,
+        }
+    }
+
+    /// Probes the SDK of a `Path` override, `None` for the other overrides.
+    ///
+    /// Only Java SDKs can be probed, so a path is an error for any other SDK type.
+    pub fn probe(&self, sdk_type: &str) -> Result<Option<LocalJdk>, Error> {
+        match self {
+            SdkOverride::Path(path) => {
+                if !jdks::supports_type(sdk_type) {
+                    return Err(Error::msg(&format!(
+                        "SDKs of type '{}' can't be given by path, only a version or range",
+                        sdk_type
+                    )));
+                }
+                Ok(Some(jdks::probe(path, JdkSource::Profile)?))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Checks the override against the game's SDK range.
+    ///
+    /// Overrides that can't work at all are errors, ones that may pick an SDK outside the range
+    /// the game declares are returned as warnings, as the user may know better.
+    pub fn check(&self, sdk_type: &str, game_versions: &VersionReq) -> Result<Vec<String>, Error> {
+        let jdk = self.probe(sdk_type)?;
+        Ok(self.warnings(sdk_type, game_versions, jdk.as_ref()))
+    }
+
+    /// Warnings for the override, `jdk` is the probed SDK of a `Path` override.
+    pub fn warnings(&self, sdk_type: &str, game_versions: &VersionReq, jdk: Option<&LocalJdk>) -> Vec<String> {
+        let mut warnings = vec![];
+        match self {
+            SdkOverride::Version(version) => {
+                if !game_versions.matches(version) {
+                    warnings.push(format!("SDK {} is outside of the game's range {}", version, game_versions));
+                }
+            }
+            SdkOverride::Range(range) => {
+                let known = sdk::known_versions(sdk_type);
+                let outside: Vec<String> = known
+                    .iter()
+                    .filter(|it| range.matches(it) && !game_versions.matches(it))
+                    .map(|it| it.to_string())
+                    .collect();
+                if !outside.is_empty() {
+                    warnings.push(format!(
+                        "Range {} allows SDK versions outside of the game's range {}: {}",
+                        range,
+                        game_versions,
+                        outside.join(", ")
+                    ));
+                }
+                if !known.iter().any(|it| range.matches(it)) {
+                    warnings.push(format!("No known {} SDK matches range {}", sdk_type, range));
+                }
+            }
+            SdkOverride::Path(path) => {
+                if let Some(jdk) = jdk {
+                    if !game_versions.matches(&jdk.version) {
+                        warnings.push(format!(
+                            "SDK {} at {} is outside of the game's range {}",
+                            jdk.version,
+                            path.to_string_lossy(),
+                            game_versions
+                        ));
+                    }
+                    if !jdk.matches_host() {
+                        warnings.push(format!("SDK at {} is built for {}", path.to_string_lossy(), jdk.arch));
+                    }
+                }
+            }
+        }
+        warnings
+    }
+}
+
+impl LaunchSettings {
+    pub fn validate(&self) -> Result<(), Error> {
+        if let (Some(min), Some(max)) = (self.min_memory, self.max_memory) {
+            if min > max {
+                return Err(Error::msg(&format!(
+                    "Minimum memory ({} MiB) is larger than maximum memory ({} MiB)",
+                    min, max
+                )));
+            }
+        }
+        if self.min_memory == Some(0) || self.max_memory == Some(0) {
+            return Err(Error::msg("Memory limits must be larger than 0 MiB"));
+        }
+        if let Some(SdkOverride::Path(path)) = &self.sdk_override {
+            if !path.is_absolute() {
+                return Err(Error::msg(&format!("SDK path must be absolute: '{}'", path.to_string_lossy())));
+            }
+        }
+        for key in self.system_properties.keys() {
+            if key.is_empty() || key.contains('=') || key.contains(char::is_whitespace) {
+                return Err(Error::msg(&format!("Invalid system property name: '{}'", key)));
+            }
+        }
+        Ok(())
+    }
+
+    /// JVM options derived from these settings, without the classpath and main class.
+    pub fn jvm_options(&self) -> Vec<String> {
+        let mut args = vec![];
+        if let Some(min) = self.min_memory {
+            args.push(format!("-Xms{}M", min));
+        }
+        if let Some(max) = self.max_memory {
+            args.push(format!("-Xmx{}M", max));
         }
+        args.extend(self.jvm_args.iter().cloned());
+        for (key, value) in self.system_properties.iter() {
+            args.push(format!("-D{}={}", key, value));
+        }
+        args
+    }
+}
+
+pub fn find(profiles: &[Profile], id: Uuid) -> Result<&Profile, Error> {
+    profiles
+        .iter()
+        .find(|profile| profile.id == id)
+        .ok_or_else(|| Error::msg(&format!("Unknown profile: {}", id)))
+}
+
+pub fn find_mut(profiles: &mut [Profile], id: Uuid) -> Result<&mut Profile, Error> {
+    profiles
+        .iter_mut()
+        .find(|profile| profile.id == id)
+        .ok_or_else(|| Error::msg(&format!("Unknown profile: {}", id)))
+}
+
+/// Gives profiles from before ids existed one, returns whether any profile changed.
+pub fn assign_ids(profiles: &mut [Profile]) -> bool {
+    let mut changed = false;
+    for profile in profiles.iter_mut().filter(|it| it.id.is_nil()) {
+        profile.id = Uuid::new_v4();
+        changed = true;
     }
+    changed
 }
 
 pub fn read_cfg(dir: &String) -> Result<GameConfig, io::Error> {
@@ -46,10 +301,36 @@ pub fn read_meta(dir: &String) -> Result<GameMetadata, io::Error> {
     Ok(meta)
 }
 
-pub fn list_zip_contents(reader: &File, name: &String) -> Result<Profile, Error> {
-    let data_dir = crate::util::get_data_dir();
+/// Extracts the game package at `path` into `data_dir`, returning the profile to add for it.
+///
+/// A package is imported by extracting it and passing the profile to [`add`], neither needs any
+/// UI.
+pub fn extract(path: &Path, name: &str, data_dir: &Path) -> Result<Profile, Error> {
+    if name.trim().is_empty() {
+        return Err(Error::msg("Profile name can't be empty"));
+    }
+    let file = File::open(path)
+        .map_err(|e| Error::msg(&format!("Failed to open package {}: {}", path.to_string_lossy(), e)))?;
+    let profile = list_zip_contents(&file, name, data_dir)?;
+    profile.validate()?;
+    Ok(profile)
+}
+
+/// Adds a profile and saves the profiles in `data_dir`, leaving `profiles` as is if that fails.
+pub fn add(profiles: &mut Vec<Profile>, profile: Profile, data_dir: &Path) -> Result<(), Error> {
+    let mut updated = profiles.clone();
+    updated.push(profile);
+    store::save_to(&store::profiles_path(data_dir), &updated)?;
+    *profiles = updated;
+    Ok(())
+}
+
+pub fn list_zip_contents(reader: &File, name: &str, data_dir: &Path) -> Result<Profile, Error> {
     let mut zip = ZipArchive::new(reader)?;
 
+    // Nothing is written before the whole package is known to be fine.
+    package::validate(&mut zip, data_dir).into_result()?;
+
     let metadata = read_metadata(&mut zip)?;
     let config = read_config(&mut zip)?;
 
@@ -58,43 +339,26 @@ pub fn list_zip_contents(reader: &File, name: &String) -> Result<Profile, Error>
 
     let game_name = config.game.as_str();
 
-    extract_single_file(
-        &mut zip,
-        data_dir
-            .join("games/".to_string() + game_name + "/versions/" + version)
-            .to_str()
-            .ok_or_else(|| {
-                Error::msg(&("Failed to extract '".to_string() + &metadata.version + ".jar)"))
-            })?,
-        &(metadata.version.to_string() + ".jar"),
-    )?;
-
-    extract_single_file(
-        &mut zip,
-        data_dir
-            .join("games/".to_string() + game_name + "/versions/" + version)
-            .to_str()
-            .unwrap(),
-        "config.json",
-    )?;
-
-    extract_single_file(
-        &mut zip,
-        data_dir
-            .join("games/".to_string() + game_name + "/versions/" + version)
-            .to_str()
-            .unwrap(),
-        "metadata.json",
-    )?;
-
-    extract_package_zip(&mut zip, &data_dir, config.classpath).unwrap_or_else(|error| {
-        show_error!("{}", error.to_string().as_str());
-    });
+    // Game and version names come from the package, so they must not escape the data dir either.
+    let version_dir = archive::safe_join(data_dir, &("games/".to_string() + game_name + "/versions/" + version))?;
+    let version_dir = version_dir
+        .to_str()
+        .ok_or_else(|| {
+            Error::msg(&("Failed to extract '".to_string() + &metadata.version + ".jar)"))
+        })?;
+
+    extract_single_file(&mut zip, version_dir, &(metadata.version.to_string() + ".jar"))?;
+    extract_single_file(&mut zip, version_dir, "config.json")?;
+    extract_single_file(&mut zip, version_dir, "metadata.json")?;
+
+    extract_package_zip(&mut zip, data_dir, config.classpath)?;
 
     let profile = Profile {
+        id: Uuid::new_v4(),
         game: game_name.to_owned(),
         name: (name).to_string(),
         version: version.to_owned(),
+        settings: Default::default(),
     };
 
     Ok(profile)
@@ -105,19 +369,13 @@ fn extract_single_file(
     archive: &mut ZipArchive<&File>,
     extract_to: &str,
     file_to_extract: &str,
-) -> Result<(), io::Error> {
+) -> Result<(), Error> {
     // Get the file at the specified index
     let mut file = archive.by_name(file_to_extract)?;
 
-    // Create the destination path
-    let dest_path = Path::new(extract_to).join(file_to_extract);
-
-    // Create directories if needed
-    if let Some(parent) = dest_path.parent() {
-        if !parent.exists() {
-            std::fs::create_dir_all(parent)?;
-        }
-    }
+    // Create the destination path, and the directories if needed
+    std::fs::create_dir_all(extract_to)?;
+    let dest_path = archive::prepare_entry(Path::new(extract_to), file_to_extract)?;
 
     // Create the file
     let mut dest_file = File::create(&dest_path)?;
@@ -133,9 +391,9 @@ fn extract_single_file(
  */
 fn extract_package_zip(
     archive: &mut ZipArchive<&File>,
-    extract_to: &PathBuf,
+    extract_to: &Path,
     files_to_extract: Vec<String>,
-) -> Result<(), io::Error> {
+) -> Result<(), Error> {
     // Iterate over each file in the zip archive
     for i in 0..archive.len() {
         let mut file = archive.by_index(i)?;
@@ -145,14 +403,9 @@ fn extract_package_zip(
 
         // Check if the file should be extracted
         if files_to_extract.contains(file_name) {
-            let dest_path = Path::new(extract_to).join(file_name);
-
-            // Create directories if needed
-            if let Some(parent) = dest_path.parent() {
-                if !parent.exists() {
-                    std::fs::create_dir_all(parent)?;
-                }
-            }
+            // Create the destination path, and the directories if needed
+            std::fs::create_dir_all(extract_to)?;
+            let dest_path = archive::prepare_entry(extract_to, file_name)?;
 
             // Create the file
             let mut dest_file = File::create(&dest_path)?;
@@ -180,3 +433,115 @@ fn read_config(zip: &mut ZipArchive<&File>) -> Result<GameConfig, Error> {
     let value = from_reader(zip_file)?;
     Ok(value)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use zip::write::FileOptions;
+    use zip::ZipWriter;
+
+    use super::*;
+    use crate::util;
+
+    #[test]
+    fn extract_package_zip_rejects_unsafe_classpath_entries() {
+        let dir = util::test_dir("profiles-package");
+        let path = dir.join("game.zip");
+        let mut zip = ZipWriter::new(File::create(&path).unwrap());
+        for name in ["libs/game.jar", "../evil.jar"] {
+            zip.start_file(name, FileOptions::default()).unwrap();
+            zip.write_all(b"data").unwrap();
+        }
+        zip.finish().unwrap();
+
+        let file = File::open(&path).unwrap();
+        let mut archive = ZipArchive::new(&file).unwrap();
+        let out = dir.join("out");
+        let classpath = vec!["libs/game.jar".to_string(), "../evil.jar".to_string()];
+
+        match extract_package_zip(&mut archive, &out, classpath) {
+            Err(Error::UnsafeArchiveEntry { entry, .. }) => assert_eq!(entry, "../evil.jar"),
+            other => panic!("Expected '../evil.jar' to be rejected, got {:?}", other),
+        }
+        assert!(out.join("libs/game.jar").is_file());
+        assert!(!dir.join("evil.jar").exists());
+    }
+
+    #[test]
+    fn path_override_requires_a_java_sdk() {
+        let sdk_override = SdkOverride::Path(util::test_dir("profiles-sdk-path"));
+        match sdk_override.probe("dotnet") {
+            Err(Error::Generic(e)) => assert!(e.to_string().contains("dotnet"), "{}", e),
+            other => panic!("Expected a path override to be rejected, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn version_override_outside_of_the_game_range_warns() {
+        let game_versions = VersionReq::parse(">=17, <22").unwrap();
+        let inside = SdkOverride::Version(Version::new(17, 0, 9));
+        let outside = SdkOverride::Version(Version::new(11, 0, 2));
+        assert!(inside.check("java", &game_versions).unwrap().is_empty());
+        assert_eq!(outside.check("java", &game_versions).unwrap().len(), 1);
+    }
+
+    fn game_config(main_class: &str) -> serde_json::Value {
+        serde_json::json!({
+            "classpath": ["libs/game.jar"],
+            "sdk": {"version": "17", "type": "java"},
+            "main-class": main_class,
+            "game": "test",
+        })
+    }
+
+    #[test]
+    fn import_extracts_and_saves_the_profile() {
+        let dir = util::test_dir("profiles-import");
+        let data_dir = dir.join("data");
+        let path = dir.join("game.zip");
+        let config = game_config("com.example.Main");
+        package::write_test_package(&path, "1.0.0", config, &["1.0.0.jar", "libs/game.jar"]);
+
+        let mut profiles = vec![];
+        let profile = extract(&path, "Main", &data_dir).unwrap();
+        add(&mut profiles, profile.clone(), &data_dir).unwrap();
+        assert_eq!(profile.game, "test");
+        assert_eq!(profile.version, "1.0.0");
+        assert_eq!(profiles.len(), 1);
+        assert!(data_dir.join("games/test/versions/1.0.0/1.0.0.jar").is_file());
+        assert!(data_dir.join("libs/game.jar").is_file());
+
+        let stored = store::load_from(&store::profiles_path(&data_dir)).unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].id, profile.id);
+    }
+
+    #[test]
+    fn import_of_an_invalid_package_leaves_the_profiles_alone() {
+        let dir = util::test_dir("profiles-import-invalid");
+        let data_dir = dir.join("data");
+        let path = dir.join("game.zip");
+        package::write_test_package(&path, "1.0.0", game_config(""), &["libs/game.jar"]);
+
+        let existing = Profile {
+            id: Uuid::new_v4(),
+            game: "test".to_string(),
+            name: "Existing".to_string(),
+            version: "0.9.0".to_string(),
+            settings: Default::default(),
+        };
+        let store_path = store::profiles_path(&data_dir);
+        store::save_to(&store_path, &[existing.clone()]).unwrap();
+        let saved = std::fs::read(&store_path).unwrap();
+
+        let mut profiles = vec![existing.clone()];
+        let imported = extract(&path, "Main", &data_dir).and_then(|it| add(&mut profiles, it, &data_dir));
+        assert!(imported.is_err());
+        assert_eq!(profiles.len(), 1);
+        assert_eq!(profiles[0].id, existing.id);
+        assert_eq!(std::fs::read(&store_path).unwrap(), saved);
+        assert!(!data_dir.join("games").exists());
+        assert!(!data_dir.join("libs").exists());
+    }
+}
@@ -0,0 +1,266 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::process::ExitStatus;
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager, Window};
+use tokio::process::Child;
+use tokio::sync::{oneshot, watch};
+use tauri::async_runtime::JoinHandle;
+use uuid::Uuid;
+
+use crate::crash::{self, CrashSummary, ExitKind};
+use crate::logs::GameLog;
+use crate::profiles::Profile;
+use crate::util::{self, Error};
+
+/// Games started by the launcher, keyed by profile id. `None` while a launch is still preparing
+/// the game, see [`RunningGames::reserve`].
+#[derive(Default)]
+pub struct RunningGames(pub Mutex<HashMap<Uuid, Option<RunningGame>>>);
+
+/// Holds a profile's registry slot from the start of a launch until the game is registered by
+/// [`watch`].
+///
+/// Dropping it before [`Reservation::complete`] frees the slot again, e.g. when the launch fails.
+pub struct Reservation {
+    app: AppHandle,
+    profile: Uuid,
+    started: bool,
+}
+
+impl Reservation {
+    /// Called once the game is registered, the slot then belongs to the game.
+    pub fn complete(mut self) {
+        self.started = true;
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        if self.started {
+            return;
+        }
+        if let Ok(mut games) = self.app.state::<RunningGames>().0.lock() {
+            if let Some(None) = games.get(&self.profile) {
+                games.remove(&self.profile);
+            }
+        }
+    }
+}
+
+pub struct RunningGame {
+    pid: u32,
+    started_at: u64,
+    kill: Option<oneshot::Sender<()>>,
+    exit: watch::Receiver<Option<GameExit>>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RunningGameInfo {
+    /// Id of the profile.
+    pub(crate) profile: Uuid,
+    pub(crate) pid: u32,
+    /// Milliseconds since the unix epoch.
+    pub(crate) started_at: u64,
+}
+
+/// Payload of the `gameExited` event.
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GameExit {
+    /// Id of the profile.
+    pub(crate) profile: Uuid,
+    pub(crate) kind: ExitKind,
+    pub(crate) code: Option<i32>,
+    pub(crate) signal: Option<i32>,
+    pub(crate) success: bool,
+    pub(crate) killed: bool,
+    /// Crash report written for abnormal exits.
+    pub(crate) crash_report: Option<PathBuf>,
+}
+
+impl RunningGames {
+    pub fn list(&self) -> Result<Vec<RunningGameInfo>, Error> {
+        let games = self.0.lock()?;
+        let mut list: Vec<RunningGameInfo> = games
+            .iter()
+            .filter_map(|(profile, game)| game.as_ref().map(|game| (profile, game)))
+            .map(|(profile, game)| RunningGameInfo {
+                profile: *profile,
+                pid: game.pid,
+                started_at: game.started_at,
+            })
+            .collect();
+        list.sort_by_key(|it| it.started_at);
+        Ok(list)
+    }
+
+    /// Whether the profile's game is running or being launched.
+    pub fn is_running(&self, profile: Uuid) -> Result<bool, Error> {
+        Ok(self.0.lock()?.contains_key(&profile))
+    }
+
+    /// Claims the profile's slot, failing if its game is already running or being launched.
+    pub fn reserve(&self, app: &AppHandle, profile: Uuid) -> Result<Reservation, Error> {
+        let mut games = self.0.lock()?;
+        if games.contains_key(&profile) {
+            return Err(Error::Launch(format!("Profile is already running: {}", profile)));
+        }
+        games.insert(profile, None);
+        Ok(Reservation {
+            app: app.to_owned(),
+            profile,
+            started: false,
+        })
+    }
+
+    pub fn kill(&self, profile: Uuid) -> Result<(), Error> {
+        let mut games = self.0.lock()?;
+        let game = games
+            .get_mut(&profile)
+            .ok_or_else(|| Error::Launch(format!("Profile is not running: {}", profile)))?
+            .as_mut()
+            .ok_or_else(|| Error::Launch(format!("Game is still starting: {}", profile)))?;
+        let kill = game
+            .kill
+            .take()
+            .ok_or_else(|| Error::Launch(format!("Game is already being stopped: {}", profile)))?;
+        println!("Killing game of profile {} (pid {})", profile, game.pid);
+        kill.send(())
+            .map_err(|_| Error::Launch(format!("Game already exited: {}", profile)))
+    }
+
+    /// Waits until the game of the profile exits.
+    pub async fn wait(&self, profile: Uuid) -> Result<GameExit, Error> {
+        let mut exit = self
+            .0
+            .lock()?
+            .get(&profile)
+            .ok_or_else(|| Error::Launch(format!("Profile is not running: {}", profile)))?
+            .as_ref()
+            .map(|game| game.exit.clone())
+            .ok_or_else(|| Error::Launch(format!("Game is still starting: {}", profile)))?;
+
+        loop {
+            let current = exit.borrow().clone();
+            if let Some(value) = current {
+                return Ok(value);
+            }
+            exit.changed()
+                .await
+                .map_err(|_| Error::Launch(format!("Lost track of game: {}", profile)))?;
+        }
+    }
+}
+
+/// Registers a freshly spawned game and watches it in the background until it exits.
+///
+/// `readers` are the tasks forwarding the game output into `log`, they are drained before the
+/// exit is reported. Crash files are collected from `game_dir`.
+pub fn watch(
+    app: &AppHandle,
+    window: &Window,
+    profile: &Profile,
+    mut child: Child,
+    readers: Vec<JoinHandle<()>>,
+    log: Arc<GameLog>,
+    game_dir: PathBuf,
+) -> Result<RunningGameInfo, Error> {
+    let profile_name = profile.name.to_string();
+    let profile = profile.id;
+    let pid = child.id().unwrap_or(0);
+    let started_at = util::now_millis();
+    let (kill_tx, mut kill_rx) = oneshot::channel();
+    let (exit_tx, exit_rx) = watch::channel(None);
+
+    let state = app.state::<RunningGames>();
+    state.0.lock()?.insert(
+        profile,
+        Some(RunningGame {
+            pid,
+            started_at,
+            kill: Some(kill_tx),
+            exit: exit_rx,
+        }),
+    );
+
+    let info = RunningGameInfo {
+        profile,
+        pid,
+        started_at,
+    };
+
+    let app = app.to_owned();
+    let window = window.to_owned();
+    tauri::async_runtime::spawn(async move {
+        let mut killed = false;
+        let status: std::io::Result<ExitStatus> = tokio::select! {
+            status = child.wait() => status,
+            Ok(()) = &mut kill_rx => {
+                killed = true;
+                match child.kill().await {
+                    Ok(()) => child.wait().await,
+                    Err(e) => Err(e),
+                }
+            }
+        };
+
+        for reader in readers {
+            if let Err(e) = reader.await {
+                println!("Game log reader failed: {:?}", e);
+            }
+        }
+
+        let status = status
+            .map_err(|e| println!("Failed to wait for game: {:?}", e))
+            .ok();
+        let tail = log.tail();
+        let crash_files = crash::find_crash_files(&game_dir, started_at);
+        let details = crash::classify(status.as_ref(), killed, &tail, &crash_files);
+
+        let mut crash_report = None;
+        if details.kind.is_abnormal() {
+            let summary = CrashSummary {
+                profile: profile.to_string(),
+                profile_name,
+                kind: details.kind,
+                code: details.code,
+                signal: details.signal,
+                started_at,
+                exited_at: util::now_millis(),
+            };
+            match crash::write_report(&summary, &tail, &crash_files) {
+                Ok(path) => crash_report = Some(path),
+                Err(e) => println!("Failed to write crash report: {:?}", e),
+            }
+        }
+
+        let exit = GameExit {
+            profile,
+            kind: details.kind,
+            code: details.code,
+            signal: details.signal,
+            success: details.kind == ExitKind::Normal,
+            killed,
+            crash_report,
+        };
+        println!("Game of profile {} exited: {:?}", profile, exit);
+
+        if let Ok(mut games) = app.state::<RunningGames>().0.lock() {
+            games.remove(&profile);
+        }
+        exit_tx.send_replace(Some(exit.clone()));
+
+        if let Err(e) = app.emit_all("gameExited", exit) {
+            println!("Failed to emit game exit event: {:?}", e);
+        }
+        if let Err(e) = window.show() {
+            println!("Failed to show window again: {:?}", e);
+        }
+    });
+
+    Ok(info)
+}
@@ -1,21 +1,25 @@
 use std::collections::HashMap;
 use std::env::consts::{ARCH, OS};
 use std::fs::File;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
 
-use flate2::read::GzDecoder;
 use reqwest::Client;
 use semver::{Version, VersionReq};
 use serde::{Deserialize, Serialize};
-use tar::Archive;
 use tauri::AppHandle;
-use zip::ZipArchive;
+use uuid::Uuid;
 
 use crate::archive;
 use crate::game::{GameConfig, GameMetadata};
+use crate::installs;
+use crate::net::{DownloadPhase, ExpectedDownload, ProgressEmitter};
+use crate::settings::LauncherSettings;
+use crate::util;
 use crate::util::Error;
 
 #[derive(Debug, Deserialize, Serialize)]
+#[allow(clippy::upper_case_acronyms)]
 pub struct SDK {
     /// Deprecated use 'versions' instead.
     #[deprecated = "Use version range using 'versions'"]
@@ -35,84 +39,131 @@ pub enum SDKOperatingSystem {
     MacOS,
 }
 
-impl Default for SDKOperatingSystem {
-    fn default() -> Self {
-        match OS {
-            "windows" => Self::Windows,
-            "linux" => Self::Linux,
-            "macos" => Self::MacOS,
-            _ => panic!("Unsupported platform!"),
+impl SDKOperatingSystem {
+    /// Operating system as reported by [`std::env::consts::OS`].
+    pub fn resolve(os: &str) -> Result<Self, Error> {
+        match os {
+            "windows" => Ok(Self::Windows),
+            "linux" => Ok(Self::Linux),
+            "macos" => Ok(Self::MacOS),
+            _ => Err(Error::Launch(format!("Unsupported operating system: {}", os))),
         }
     }
+
+    /// Operating system the launcher runs on.
+    pub fn current() -> Result<Self, Error> {
+        Self::resolve(OS)
+    }
 }
 
 impl Eq for SDKOperatingSystem {}
 
-#[derive(Debug, Deserialize, Hash, PartialEq)]
+#[derive(Debug, Deserialize, Hash, PartialEq, Clone, Copy)]
 pub enum SDKPlatform {
     #[serde(alias = "win-x64")]
     WinX64,
     #[serde(alias = "win-x86")]
     WinX86,
+    #[serde(alias = "win-arm64", alias = "win-aarch64")]
+    WinArm64,
     #[serde(alias = "lin-x64")]
     LinX64,
-    #[serde(alias = "lin-arm")]
+    #[serde(alias = "lin-x86")]
+    LinX86,
+    /// 64-bit ARM, the only ARM architecture SDKs are published for.
+    #[serde(alias = "lin-arm", alias = "lin-arm64", alias = "lin-aarch64")]
     LinArm,
     #[serde(alias = "mac-x64")]
     MacX64,
-    #[serde(alias = "mac-arm")]
+    #[serde(alias = "mac-arm", alias = "mac-arm64", alias = "mac-aarch64")]
     MacArm,
 }
 
-impl Default for SDKPlatform {
-    fn default() -> Self {
-        match OS {
-            "windows" => match ARCH {
-                "x86" => {
-                    Self::WinX86
-                }
-                "x86_64" => {
-                    Self::WinX64
-                }
-                _ => {
-                    panic!("Unsupported platform!");
-                }
-            },
-            "linux" => match ARCH {
-                "x86_64" => {
-                    Self::LinX64
-                }
-                "arm" => {
-                    Self::LinArm
-                }
-                _ => {
-                    panic!("Unsupported platform!");
-                }
-            },
-            "macos" => match ARCH {
-                "x86_64" => {
-                    Self::MacX64
-                }
-                "arm" => {
-                    Self::MacArm
-                }
-                _ => {
-                    panic!("Unsupported platform!");
-                }
-            },
-            _ => {
-                panic!("Unsupported platform!");
-            }
+impl SDKPlatform {
+    /// Native platform for an operating system and architecture, as reported by
+    /// [`std::env::consts`].
+    pub fn resolve(os: &str, arch: &str) -> Result<Self, Error> {
+        match (os, arch) {
+            ("windows", "x86_64") => Ok(Self::WinX64),
+            ("windows", "x86") => Ok(Self::WinX86),
+            ("windows", "aarch64") => Ok(Self::WinArm64),
+            ("linux", "x86_64") => Ok(Self::LinX64),
+            ("linux", "x86") => Ok(Self::LinX86),
+            ("linux", "aarch64") => Ok(Self::LinArm),
+            ("macos", "x86_64") => Ok(Self::MacX64),
+            ("macos", "aarch64") => Ok(Self::MacArm),
+            _ => Err(Error::Launch(format!("Unsupported platform: {} {}", os, arch))),
+        }
+    }
+
+    /// Platforms whose SDKs can run on this operating system and architecture, native first and
+    /// then the ones that run under emulation (Rosetta on macOS, x64 emulation on Windows on ARM).
+    pub fn candidates(os: &str, arch: &str) -> Result<Vec<Self>, Error> {
+        let native = Self::resolve(os, arch)?;
+        let mut candidates = vec![native];
+        match native {
+            Self::WinArm64 => candidates.extend([Self::WinX64, Self::WinX86]),
+            Self::WinX64 => candidates.push(Self::WinX86),
+            Self::MacArm => candidates.push(Self::MacX64),
+            _ => {}
+        }
+        Ok(candidates)
+    }
+
+    /// Architecture in [`std::env::consts::ARCH`] terms.
+    pub fn arch(&self) -> &'static str {
+        match self {
+            Self::WinX64 | Self::LinX64 | Self::MacX64 => "x86_64",
+            Self::WinX86 | Self::LinX86 => "x86",
+            Self::WinArm64 | Self::LinArm | Self::MacArm => "aarch64",
         }
     }
+
+    /// Platforms that can run on the machine the launcher runs on.
+    pub fn current() -> Result<Vec<Self>, Error> {
+        Self::candidates(OS, ARCH)
+    }
 }
 
 impl Eq for SDKPlatform {}
 
 #[derive(Deserialize)]
-pub struct SDKDownloadInfo(HashMap<SDKPlatform, String>);
+pub struct SDKDownloadInfo(HashMap<SDKPlatform, SDKDownload>);
 
-#[derive(Deserialize)]
+/// Download of a single platform, either a plain URL or a URL with its expected hash and size.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum SDKDownload {
+    Url(String),
+    Verified {
+        url: String,
+        #[serde(default)]
+        sha256: Option<String>,
+        #[serde(default)]
+        size: Option<u64>,
+    },
+}
+
+impl SDKDownload {
+    pub fn url(&self) -> &str {
+        match self {
+            SDKDownload::Url(url) => url,
+            SDKDownload::Verified { url, .. } => url,
+        }
+    }
+
+    pub fn expected(&self) -> ExpectedDownload {
+        match self {
+            SDKDownload::Url(_) => ExpectedDownload::default(),
+            SDKDownload::Verified { sha256, size, .. } => ExpectedDownload {
+                sha256: sha256.to_owned(),
+                size: size.to_owned(),
+            },
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
 pub struct SDKExecutablePaths(HashMap<SDKOperatingSystem, String>);
 
 #[derive(Deserialize)]
@@ -124,9 +175,44 @@ pub struct SDKInfo {
     date: String,
     executable_path: String,
     #[serde(default)]
-    executable_paths: HashMap<SDKOperatingSystem, String>,
+    executable_paths: SDKExecutablePaths,
     #[serde(default)]
     pub(crate) inner_path: Option<String>,
+    /// Whether a single top level directory of the package is removed during installation.
+    #[serde(default = "default_strip_root")]
+    pub(crate) strip_root: bool,
+}
+
+fn default_strip_root() -> bool {
+    true
+}
+
+impl SDKInfo {
+    /// Path of the SDK executable relative to the SDK home, for the current operating system.
+    pub fn executable(&self) -> Result<&str, Error> {
+        Ok(self
+            .executable_paths
+            .0
+            .get(&SDKOperatingSystem::current()?)
+            .unwrap_or(&self.executable_path))
+    }
+
+    pub fn resolve(&self, sdk_type: &str) -> Result<ResolvedSdk, Error> {
+        let install_dir = sdk_dir(sdk_type, &self.version);
+        let home = match read_marker(&install_dir).ok().and_then(|it| it.java_home) {
+            Some(java_home) => install_dir.join(java_home),
+            // Installs from before the home was recorded
+            None => match &self.inner_path {
+                Some(inner_path) => install_dir.join(inner_path),
+                None => install_dir,
+            },
+        };
+        Ok(ResolvedSdk {
+            version: self.version.clone(),
+            executable: home.join(self.executable()?),
+            home,
+        })
+    }
 }
 
 #[derive(Deserialize)]
@@ -139,67 +225,603 @@ pub async fn retrieve_sdk(
     sdk_info: &SDKInfo,
     cfg: &GameConfig,
     _meta: &GameMetadata,
-) -> Result<bool, String> {
+    settings: &LauncherSettings,
+) -> Result<bool, Error> {
     let app = app_;
 
-    let platform = &Default::default();
-
-    let url = sdk_info
-        .download
-        .0
-        .get(platform)
-        .ok_or_else(|| format!("Can't find SDK for platform {:?}", platform))?;
+    let platforms = SDKPlatform::current()?;
+    let (platform, download) = platforms
+        .iter()
+        .find_map(|platform| sdk_info.download.0.get(platform).map(|it| (platform, it)))
+        .ok_or_else(|| Error::Launch(format!("Can't find SDK for platform {:?}", platforms[0])))?;
+    if *platform != platforms[0] {
+        println!("No {:?} SDK available, using {:?} under emulation", platforms[0], platform);
+    }
+    let url = download.url();
     let name = url.rsplit_once('/').map(|v| v.1).unwrap_or(url);
 
     let data_dir = &crate::util::get_data_dir();
 
-    let output_dir = data_dir
-        .join("sdks/".to_string() + &cfg.sdk.r#type + "/" + &sdk_info.version.to_string())
-        .to_str()
-        .unwrap()
-        .to_string();
-
-    if Path::new(&output_dir).exists() {
+    let install_dir = sdk_dir(&cfg.sdk.r#type, &sdk_info.version);
+    if is_installed(&install_dir) {
         return Ok(false);
     }
 
     let file_path = &data_dir.join(format!("temp/{}", name));
 
     std::fs::create_dir_all(data_dir.join("temp"))
-        .map_err(|e| format!("Failed to create output directory: {:?}", e))?;
+        .map_err(|e| Error::Launch(format!("Failed to create output directory: {:?}", e)))?;
 
-    crate::net::download_file(
-        app.to_owned(),
-        client,
-        url.to_string(),
-        file_path.to_owned(),
-    )
-        .await
-        .map_err(|e| format!("Failed to download SDK: {:?}", e))?;
+    let (source_url, sha256) = download_sdk(app.to_owned(), client, download, file_path, settings).await?;
 
-    let file = File::open(file_path).map_err(|e| format!("Failed to open SDK package: {:?}", e))?;
-    if file_path.file_name().unwrap().to_string_lossy().ends_with(".tar.gz") {
-        let decompressed = GzDecoder::new(file);
-        let mut archive = Archive::new(decompressed);
-        archive::extract_tar_gz(app, name, &output_dir, &mut archive)?;
-    } else if file_path.file_name().unwrap().to_string_lossy().ends_with(".zip") {
-        let archive = &mut ZipArchive::new(file).map_err(|e| format!("Failed to open SDK package: {:?}", e))?;
-        archive::extract_zip(app, name, &output_dir, archive)?;
+    // Extract next to the final location, so a failed or interrupted install never looks complete.
+    let staging_dir = staging_root().join(format!(
+        "{}-{}-{}",
+        util::sanitize_file_name(&cfg.sdk.r#type),
+        sdk_info.version,
+        Uuid::new_v4()
+    ));
+    std::fs::create_dir_all(&staging_dir)
+        .map_err(|e| Error::Launch(format!("Failed to create staging directory: {:?}", e)))?;
+    let output_dir = staging_dir.to_str().unwrap().to_string();
+
+    if let Err(e) = extract_package(app.to_owned(), name, file_path, &output_dir) {
+        if let Err(e) = std::fs::remove_dir_all(&staging_dir) {
+            println!("Failed to clean up staging directory: {:?}", e);
+        }
+        return Err(e);
     }
 
-    std::fs::remove_file(file_path).map_err(|e| format!("Failed to clean up downloaded SDK package: {:?}", e))?;
+    std::fs::remove_file(file_path).map_err(|e| Error::Launch(format!("Failed to clean up downloaded SDK package: {:?}", e)))?;
+
+    let marker = InstallMarker {
+        version: sdk_info.version.clone(),
+        url: source_url,
+        sha256,
+        installed_at: util::now_millis(),
+        java_home: None,
+        executable: Some(sdk_info.executable()?.to_string()),
+        stripped_root: None,
+        tree_sha256: None,
+    };
+    let mut progress = ProgressEmitter::new(&app, DownloadPhase::Finalising, format!("Installing: {}", name));
+    progress.emit(0, 1, true);
+    if let Err(e) = finish_install(sdk_info, &staging_dir, &install_dir, marker) {
+        if staging_dir.exists() {
+            if let Err(e) = std::fs::remove_dir_all(&staging_dir) {
+                println!("Failed to clean up staging directory: {:?}", e);
+            }
+        }
+        return Err(e);
+    }
+    println!("Installed SDK {} into {}", sdk_info.version, install_dir.to_string_lossy());
+    progress.complete();
 
     Ok(true)
 }
 
-pub async fn fetch_sdk(client: Client) -> Result<SDKList, Error> {
-    let value: SDKList = serde_json::from_slice(
-        &client
-            .get("https://ultreon.github.io/metadata/sdks.json")
-            .send()
-            .await?
-            .bytes()
-            .await?,
-    )?;
+/// Strips the package's top level directory if there is one, writes the install marker and moves
+/// the extracted SDK into place.
+fn finish_install(sdk_info: &SDKInfo, staging_dir: &Path, install_dir: &Path, mut marker: InstallMarker) -> Result<(), Error> {
+    let root = if sdk_info.strip_root { single_root(staging_dir)? } else { None };
+    marker.stripped_root = root
+        .as_ref()
+        .and_then(|it| it.file_name())
+        .map(|it| it.to_string_lossy().to_string());
+    let content_dir = root.unwrap_or_else(|| staging_dir.to_path_buf());
+
+    let executable = sdk_info.executable()?;
+    let java_home = find_java_home(&content_dir, sdk_info, executable, marker.stripped_root.as_deref())
+        .ok_or_else(|| Error::Launch(format!("SDK package doesn't contain {}", executable)))?;
+    println!("SDK home: '{}', stripped: {:?}", java_home, marker.stripped_root);
+    marker.java_home = Some(java_home);
+    marker.tree_sha256 = Some(installs::tree_hash(&content_dir)?);
+    serde_json::to_writer_pretty(File::create(content_dir.join(INSTALL_MARKER))?, &marker)?;
+
+    // Anything left at the final location is incomplete, usable legacy installs got a marker in
+    // `is_installed` before the download started.
+    if install_dir.exists() {
+        println!("Removing incomplete SDK install: {}", install_dir.to_string_lossy());
+        std::fs::remove_dir_all(install_dir)?;
+    }
+    if let Some(parent) = install_dir.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::rename(&content_dir, install_dir)
+        .map_err(|e| Error::Launch(format!("Failed to move SDK into place: {:?}", e)))?;
+    if staging_dir.exists() {
+        std::fs::remove_dir_all(staging_dir)?;
+    }
+    Ok(())
+}
+
+/// The only entry of the directory, if it is a directory itself.
+fn single_root(dir: &Path) -> Result<Option<PathBuf>, Error> {
+    let mut entries = std::fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+    if entries.len() != 1 {
+        return Ok(None);
+    }
+    let entry = entries.remove(0);
+    if entry.file_type()?.is_dir() {
+        Ok(Some(entry.path()))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Finds the directory containing the SDK executable, relative to `dir`.
+fn find_java_home(dir: &Path, sdk_info: &SDKInfo, executable: &str, stripped_root: Option<&str>) -> Option<String> {
+    let mut candidates = vec![];
+    if let Some(inner_path) = &sdk_info.inner_path {
+        // `inner_path` usually names the root folder that just got stripped.
+        if let Some(stripped) = stripped_root.and_then(|root| Path::new(inner_path).strip_prefix(root).ok()) {
+            candidates.push(stripped.to_string_lossy().to_string());
+        }
+        candidates.push(inner_path.to_string());
+    }
+    candidates.push("".to_string());
+    candidates.push("Contents/Home".to_string());
+
+    candidates
+        .into_iter()
+        .find(|it| dir.join(it).join(executable).is_file())
+}
+
+fn extract_package(app: AppHandle, name: &str, file_path: &Path, output_dir: &str) -> Result<(), Error> {
+    let extractor = archive::extractor_for(file_path)?;
+    println!("Extracting {} as {}", name, extractor.format());
+    extractor.extract(app, name, file_path, output_dir)
+}
+
+/// Name of the file marking an SDK install as complete.
+pub const INSTALL_MARKER: &str = ".install.json";
+
+/// Written into an SDK directory as the very last step of installing it.
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct InstallMarker {
+    pub(crate) version: Version,
+    /// URL the SDK package was downloaded from.
+    pub(crate) url: String,
+    /// SHA-256 of the downloaded SDK package.
+    pub(crate) sha256: String,
+    /// Milliseconds since the unix epoch.
+    pub(crate) installed_at: u64,
+    /// Directory containing the SDK's `bin` folder, relative to the install directory.
+    #[serde(default)]
+    pub(crate) java_home: Option<String>,
+    /// Path of the SDK executable relative to the home, as the index listed it at install time.
+    #[serde(default)]
+    pub(crate) executable: Option<String>,
+    /// Top level directory of the package that was stripped during installation.
+    #[serde(default)]
+    pub(crate) stripped_root: Option<String>,
+    /// SHA-256 of the installed files, see [`installs::tree_hash`].
+    #[serde(default)]
+    pub(crate) tree_sha256: Option<String>,
+}
+
+pub fn read_marker(install_dir: &Path) -> Result<InstallMarker, Error> {
+    let file = File::open(install_dir.join(INSTALL_MARKER))?;
+    Ok(serde_json::from_reader(file)?)
+}
+
+/// Reads the install marker, first writing one for an install from before markers existed if its
+/// executable can be found. Legacy installs without an executable are left alone as incomplete.
+pub fn read_or_migrate_marker(install_dir: &Path) -> Result<InstallMarker, Error> {
+    if install_dir.join(INSTALL_MARKER).exists() {
+        return read_marker(install_dir);
+    }
+    let version = Version::parse(&install_dir.file_name().unwrap_or_default().to_string_lossy())
+        .map_err(|e| Error::msg(&format!("Not an SDK install: {}: {}", install_dir.to_string_lossy(), e)))?;
+    let executable = default_executable();
+    let home = find_home(install_dir, executable).ok_or_else(|| {
+        Error::msg(&format!("Incomplete SDK install: {}", install_dir.to_string_lossy()))
+    })?;
+
+    let marker = InstallMarker {
+        version,
+        // Where legacy installs came from wasn't recorded
+        url: String::new(),
+        sha256: String::new(),
+        installed_at: std::fs::metadata(install_dir)?
+            .modified()
+            .ok()
+            .and_then(|it| it.duration_since(UNIX_EPOCH).ok())
+            .map(|it| it.as_millis() as u64)
+            .unwrap_or(0),
+        java_home: Some(
+            home.strip_prefix(install_dir)
+                .map(|it| it.to_string_lossy().to_string())
+                .unwrap_or_default(),
+        ),
+        executable: Some(executable.to_string()),
+        stripped_root: None,
+        tree_sha256: Some(installs::tree_hash(install_dir)?),
+    };
+    util::write_atomic(&install_dir.join(INSTALL_MARKER), &serde_json::to_vec_pretty(&marker)?)?;
+    println!("Migrated SDK install from before install markers: {}", install_dir.to_string_lossy());
+    Ok(marker)
+}
+
+pub fn is_installed(install_dir: &Path) -> bool {
+    read_or_migrate_marker(install_dir).is_ok()
+}
+
+fn staging_root() -> PathBuf {
+    util::get_data_dir().join("sdks/.staging")
+}
+
+/// Removes SDK installs that were interrupted, called on startup before any install can run.
+pub fn clean_staging() -> Result<(), Error> {
+    let root = staging_root();
+    if !root.exists() {
+        return Ok(());
+    }
+    for entry in std::fs::read_dir(&root)? {
+        let path = entry?.path();
+        println!("Removing interrupted SDK install: {}", path.to_string_lossy());
+        if path.is_dir() {
+            std::fs::remove_dir_all(&path)?;
+        } else {
+            std::fs::remove_file(&path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Downloads the SDK package, trying the configured mirrors before the original URL.
+///
+/// Returns the URL that worked and the SHA-256 of the package.
+async fn download_sdk(
+    app: AppHandle,
+    client: Client,
+    download: &SDKDownload,
+    file_path: &Path,
+    settings: &LauncherSettings,
+) -> Result<(String, String), Error> {
+    let mut last_error = None;
+    for url in settings.download_urls(download.url()) {
+        println!("Downloading SDK from: {}", url);
+        match crate::net::download_file(
+            app.to_owned(),
+            client.to_owned(),
+            url.to_string(),
+            file_path.to_path_buf(),
+            &download.expected(),
+            &settings.retry_policy(),
+        )
+            .await
+        {
+            Ok(sha256) => return Ok((url, sha256)),
+            Err(e) => {
+                println!("Failed to download SDK from {}: {:?}", url, e);
+                last_error = Some(e);
+            }
+        }
+    }
+
+    Err(match last_error {
+        Some(e @ Error::ChecksumMismatch { .. }) => e,
+        Some(e) => Error::Download(format!("Failed to download SDK: {:?}", e)),
+        None => Error::Download("No download URL for SDK".to_string()),
+    })
+}
+
+/// SDK index as stored on disk, used when the index can't be fetched.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CachedSDKList {
+    /// Milliseconds since the unix epoch.
+    fetched_at: u64,
+    index: SDKList,
+}
+
+fn index_cache_path() -> PathBuf {
+    crate::util::get_data_dir().join("cache/sdks.json")
+}
+
+/// Fetches the SDK index, falling back to the cached copy when the network is unavailable.
+pub async fn fetch_sdk(client: Client, settings: &LauncherSettings) -> Result<SDKList, Error> {
+    match fetch_sdk_index(client, &settings.sdk_index_url).await {
+        Ok(value) => Ok(value),
+        Err(e) => {
+            println!("Failed to fetch SDK index, using cached copy: {:?}", e);
+            let cached: CachedSDKList = serde_json::from_reader(File::open(index_cache_path()).map_err(|_| {
+                Error::Fetch(format!("Failed to fetch SDK index and no cached copy exists: {:?}", e))
+            })?)?;
+            let age = util::now_millis()
+                .saturating_sub(cached.fetched_at);
+            println!("Using SDK index cached {} minutes ago", age / 60_000);
+            Ok(cached.index)
+        }
+    }
+}
+
+async fn fetch_sdk_index(client: Client, url: &str) -> Result<SDKList, Error> {
+    println!("Fetching SDK index: {}", url);
+    let bytes = crate::net::fetch_bytes(&client, url).await?;
+    let value: SDKList = serde_json::from_slice(&bytes)?;
+
+    if let Err(e) = write_index_cache(&bytes) {
+        println!("Failed to cache SDK index: {:?}", e);
+    }
     Ok(value)
 }
+
+fn write_index_cache(bytes: &[u8]) -> Result<(), Error> {
+    let index: serde_json::Value = serde_json::from_slice(bytes)?;
+    let fetched_at = util::now_millis();
+
+    let data = serde_json::to_vec(&serde_json::json!({ "fetchedAt": fetched_at, "index": index }))?;
+    util::write_atomic(&index_cache_path(), &data)
+}
+
+/// SDK types in the cached index or installed, without fetching the index.
+pub fn known_types() -> Vec<String> {
+    let mut types: Vec<String> = File::open(index_cache_path())
+        .ok()
+        .and_then(|file| serde_json::from_reader::<_, CachedSDKList>(file).ok())
+        .map(|cached| cached.index.0.into_keys().collect())
+        .unwrap_or_default();
+    let installed = std::fs::read_dir(util::get_data_dir().join("sdks"))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|it| it.path().is_dir())
+        .map(|it| it.file_name().to_string_lossy().to_string())
+        .filter(|it| !it.starts_with('.'));
+    for sdk_type in installed {
+        if !types.contains(&sdk_type) {
+            types.push(sdk_type);
+        }
+    }
+    types
+}
+
+/// SDK versions of a type that are installed or listed in the cached index, without fetching it.
+pub fn known_versions(sdk_type: &str) -> Vec<Version> {
+    let mut versions: Vec<Version> = File::open(index_cache_path())
+        .ok()
+        .and_then(|file| serde_json::from_reader::<_, CachedSDKList>(file).ok())
+        .and_then(|cached| cached.index.0.get(sdk_type).map(|it| it.values().map(|it| it.version.clone()).collect()))
+        .unwrap_or_default();
+    let installed = std::fs::read_dir(util::get_data_dir().join("sdks").join(sdk_type))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|it| Version::parse(&it.file_name().to_string_lossy()).ok());
+    for version in installed {
+        if !versions.contains(&version) {
+            versions.push(version);
+        }
+    }
+    versions.sort();
+    versions
+}
+
+/// Picks the highest SDK version of the index that matches the version range.
+pub fn select_sdk<'a>(sdk_list: &'a SDKList, sdk_type: &str, versions: &VersionReq) -> Result<&'a SDKInfo, Error> {
+    let sdk_info_map = sdk_list
+        .0
+        .get(sdk_type)
+        .ok_or_else(|| Error::Launch(format!("Unknown SDK type: {}", sdk_type)))?;
+
+    let mut sdk_info: Option<&SDKInfo> = None;
+    for ele in sdk_info_map {
+        let v = &ele.1.version;
+        let is_newer_supported = sdk_info.is_some()
+            && versions.matches(v)
+            && sdk_info.unwrap().version < *v;
+        let is_supported = sdk_info.is_none() && versions.matches(v);
+        if (is_supported) || (is_newer_supported) {
+            sdk_info = Some(ele.1);
+        }
+    }
+    sdk_info.ok_or_else(|| {
+        Error::Launch(format!("No compatible versions found: {}", sdk_type))
+    })
+}
+
+/// An SDK on disk that can be used to launch a game.
+pub struct ResolvedSdk {
+    pub(crate) version: Version,
+    /// Directory containing the SDK's `bin` folder.
+    pub(crate) home: PathBuf,
+    pub(crate) executable: PathBuf,
+}
+
+/// Executable used when the index doesn't say otherwise, e.g. for SDKs found on disk.
+pub fn default_executable() -> &'static str {
+    if cfg!(target_os = "windows") {
+        "bin/javaw.exe"
+    } else {
+        "bin/java"
+    }
+}
+
+fn sdk_dir(sdk_type: &str, version: &Version) -> PathBuf {
+    crate::util::get_data_dir()
+        .join("sdks")
+        .join(sdk_type)
+        .join(version.to_string())
+}
+
+/// Finds the highest installed SDK matching the version range, without needing the index.
+pub fn find_installed(sdk_type: &str, versions: &VersionReq) -> Option<ResolvedSdk> {
+    let dir = crate::util::get_data_dir().join("sdks").join(sdk_type);
+    let mut best: Option<ResolvedSdk> = None;
+    for entry in std::fs::read_dir(dir).ok()?.flatten() {
+        let version = match Version::parse(&entry.file_name().to_string_lossy()) {
+            Ok(version) => version,
+            Err(_) => continue,
+        };
+        if !versions.matches(&version) || best.as_ref().map(|it| it.version >= version).unwrap_or(false) {
+            continue;
+        }
+        let marker = match read_or_migrate_marker(&entry.path()) {
+            Ok(marker) => marker,
+            Err(_) => {
+                println!("Ignoring incomplete SDK install: {}", entry.path().to_string_lossy());
+                continue;
+            }
+        };
+        // Markers from before the executable was recorded
+        let executable = marker.executable.unwrap_or_else(|| default_executable().to_string());
+        let recorded_home = marker.java_home.map(|it| entry.path().join(it));
+        if let Some(home) = recorded_home.or_else(|| find_home(&entry.path(), &executable)) {
+            best = Some(ResolvedSdk {
+                version,
+                executable: home.join(executable),
+                home,
+            });
+        }
+    }
+
+    if let Some(sdk) = &best {
+        println!("Found installed SDK {} at {}", sdk.version, sdk.home.to_string_lossy());
+    }
+    best
+}
+
+/// Locates the SDK home inside an install directory, archives often wrap it in a folder.
+fn find_home(dir: &Path, executable: &str) -> Option<PathBuf> {
+    let mut candidates = vec![dir.to_path_buf()];
+    let children: Vec<PathBuf> = std::fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .map(|it| it.path())
+        .filter(|it| it.is_dir())
+        .collect();
+    if let [child] = children.as_slice() {
+        candidates.push(child.to_path_buf());
+        candidates.push(child.join("Contents/Home"));
+    }
+    candidates
+        .into_iter()
+        .find(|it| it.join(executable).is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use SDKPlatform::*;
+
+    #[test]
+    fn resolves_every_supported_platform() {
+        let table = [
+            ("windows", "x86_64", WinX64, vec![WinX64, WinX86]),
+            ("windows", "x86", WinX86, vec![WinX86]),
+            ("windows", "aarch64", WinArm64, vec![WinArm64, WinX64, WinX86]),
+            ("linux", "x86_64", LinX64, vec![LinX64]),
+            ("linux", "x86", LinX86, vec![LinX86]),
+            ("linux", "aarch64", LinArm, vec![LinArm]),
+            ("macos", "x86_64", MacX64, vec![MacX64]),
+            ("macos", "aarch64", MacArm, vec![MacArm, MacX64]),
+        ];
+        for (os, arch, native, candidates) in table {
+            assert_eq!(SDKPlatform::resolve(os, arch).unwrap(), native, "{} {}", os, arch);
+            assert_eq!(SDKPlatform::candidates(os, arch).unwrap(), candidates, "{} {}", os, arch);
+            assert_eq!(native.arch(), arch);
+        }
+    }
+
+    #[test]
+    fn emulated_platforms_come_after_the_native_one() {
+        assert_eq!(SDKPlatform::candidates("macos", "aarch64").unwrap()[1..], [MacX64]);
+        assert_eq!(SDKPlatform::candidates("windows", "aarch64").unwrap()[1..], [WinX64, WinX86]);
+        // Emulation only goes one way
+        assert!(!SDKPlatform::candidates("macos", "x86_64").unwrap().contains(&MacArm));
+        assert!(!SDKPlatform::candidates("windows", "x86_64").unwrap().contains(&WinArm64));
+    }
+
+    #[test]
+    fn rejects_unsupported_platforms() {
+        for (os, arch) in [
+            ("freebsd", "x86_64"),
+            ("linux", "arm"),
+            ("linux", "riscv64"),
+            ("macos", "x86"),
+            ("windows", "arm"),
+        ] {
+            assert!(SDKPlatform::resolve(os, arch).is_err(), "{} {}", os, arch);
+            assert!(SDKPlatform::candidates(os, arch).is_err(), "{} {}", os, arch);
+        }
+    }
+
+    #[test]
+    fn resolves_operating_systems() {
+        assert_eq!(SDKOperatingSystem::resolve("windows").unwrap(), SDKOperatingSystem::Windows);
+        assert_eq!(SDKOperatingSystem::resolve("linux").unwrap(), SDKOperatingSystem::Linux);
+        assert_eq!(SDKOperatingSystem::resolve("macos").unwrap(), SDKOperatingSystem::MacOS);
+        assert!(SDKOperatingSystem::resolve("freebsd").is_err());
+    }
+
+    #[test]
+    fn deserializes_platform_aliases() {
+        let table = [
+            (WinX64, vec!["WinX64", "win-x64"]),
+            (WinX86, vec!["WinX86", "win-x86"]),
+            (WinArm64, vec!["WinArm64", "win-arm64", "win-aarch64"]),
+            (LinX64, vec!["LinX64", "lin-x64"]),
+            (LinX86, vec!["LinX86", "lin-x86"]),
+            (LinArm, vec!["LinArm", "lin-arm", "lin-arm64", "lin-aarch64"]),
+            (MacX64, vec!["MacX64", "mac-x64"]),
+            (MacArm, vec!["MacArm", "mac-arm", "mac-arm64", "mac-aarch64"]),
+        ];
+        for (platform, names) in table {
+            for name in names {
+                let parsed: SDKPlatform = serde_json::from_value(serde_json::json!(name)).unwrap();
+                assert_eq!(parsed, platform, "{}", name);
+            }
+        }
+        assert!(serde_json::from_value::<SDKPlatform>(serde_json::json!("lin-riscv64")).is_err());
+    }
+
+    #[test]
+    fn deserializes_operating_system_aliases() {
+        let table = [
+            (SDKOperatingSystem::Windows, ["Windows", "win"]),
+            (SDKOperatingSystem::Linux, ["Linux", "lin"]),
+            (SDKOperatingSystem::MacOS, ["MacOS", "mac"]),
+        ];
+        for (os, names) in table {
+            for name in names {
+                let parsed: SDKOperatingSystem = serde_json::from_value(serde_json::json!(name)).unwrap();
+                assert_eq!(parsed, os, "{}", name);
+            }
+        }
+    }
+
+    #[test]
+    fn download_keys_accept_aliases() {
+        let info: SDKDownloadInfo =
+            serde_json::from_str(r#"{"lin-aarch64": "https://example.com/jdk.tar.gz", "mac-x64": "https://example.com/jdk.zip"}"#)
+                .unwrap();
+        assert_eq!(info.0[&LinArm].url(), "https://example.com/jdk.tar.gz");
+        assert_eq!(info.0[&MacX64].url(), "https://example.com/jdk.zip");
+    }
+
+    #[test]
+    fn legacy_installs_with_an_executable_are_migrated() {
+        let dir = util::test_dir("sdk-legacy").join("17.0.2");
+        let home = dir.join("jdk-17.0.2");
+        std::fs::create_dir_all(home.join("bin")).unwrap();
+        std::fs::write(home.join(default_executable()), b"").unwrap();
+
+        let marker = read_or_migrate_marker(&dir).unwrap();
+        assert_eq!(marker.version, Version::new(17, 0, 2));
+        assert_eq!(marker.java_home.as_deref(), Some("jdk-17.0.2"));
+        assert_eq!(marker.executable.as_deref(), Some(default_executable()));
+        assert!(read_marker(&dir).is_ok());
+    }
+
+    #[test]
+    fn legacy_installs_without_an_executable_stay_incomplete() {
+        let dir = util::test_dir("sdk-incomplete").join("17.0.2");
+        std::fs::create_dir_all(dir.join("jdk-17.0.2/lib")).unwrap();
+
+        assert!(read_or_migrate_marker(&dir).is_err());
+        assert!(!dir.join(INSTALL_MARKER).exists());
+    }
+}
@@ -0,0 +1,207 @@
+use std::fs::File;
+use std::path::PathBuf;
+
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+use crate::net::RetryPolicy;
+use crate::util::{self, Error};
+
+pub const DEFAULT_SDK_INDEX_URL: &str = "https://ultreon.github.io/metadata/sdks.json";
+
+/// Launcher wide settings, stored in `settings.json` in the data directory.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LauncherSettings {
+    /// URL of the SDK index, `file://` URLs are read from disk.
+    #[serde(default = "default_sdk_index_url")]
+    pub sdk_index_url: String,
+    /// Mirrors tried in order before the original download URL.
+    #[serde(default)]
+    pub mirrors: Vec<Mirror>,
+    /// How often a failed download is retried before giving up.
+    #[serde(default = "default_max_download_retries")]
+    pub max_download_retries: u32,
+    /// Use a compatible JDK found on the system before downloading one.
+    #[serde(default)]
+    pub prefer_local_jdks: bool,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Mirror {
+    /// Base URL of the mirror.
+    pub url: String,
+    /// Prefix of the download URLs this mirror replaces. When missing, only the scheme and host
+    /// of every download URL are replaced by the mirror's base URL.
+    #[serde(default)]
+    pub from: Option<String>,
+}
+
+fn default_sdk_index_url() -> String {
+    DEFAULT_SDK_INDEX_URL.to_string()
+}
+
+fn default_max_download_retries() -> u32 {
+    RetryPolicy::default().max_retries
+}
+
+impl Default for LauncherSettings {
+    fn default() -> Self {
+        LauncherSettings {
+            sdk_index_url: default_sdk_index_url(),
+            mirrors: vec![],
+            max_download_retries: default_max_download_retries(),
+            prefer_local_jdks: false,
+        }
+    }
+}
+
+impl LauncherSettings {
+    pub fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            max_retries: self.max_download_retries,
+            ..Default::default()
+        }
+    }
+
+    /// URLs to try for a download, mirrors first and the original URL last.
+    pub fn download_urls(&self, url: &str) -> Vec<String> {
+        let mut urls = vec![];
+        for mirror in self.mirrors.iter() {
+            if let Some(rewritten) = mirror.rewrite(url) {
+                if !urls.contains(&rewritten) {
+                    urls.push(rewritten);
+                }
+            }
+        }
+        if !urls.iter().any(|it| it == url) {
+            urls.push(url.to_string());
+        }
+        urls
+    }
+
+    pub fn validate(&self) -> Result<(), Error> {
+        Url::parse(&self.sdk_index_url)
+            .map_err(|e| Error::msg(&format!("Invalid SDK index URL '{}': {}", self.sdk_index_url, e)))?;
+        for mirror in self.mirrors.iter() {
+            Url::parse(&mirror.url)
+                .map_err(|e| Error::msg(&format!("Invalid mirror URL '{}': {}", mirror.url, e)))?;
+        }
+        Ok(())
+    }
+}
+
+impl Mirror {
+    fn rewrite(&self, url: &str) -> Option<String> {
+        let base = self.url.trim_end_matches('/');
+        match &self.from {
+            Some(from) => url
+                .strip_prefix(from.as_str())
+                .map(|rest| format!("{}/{}", base, rest.trim_start_matches('/'))),
+            None => {
+                let parsed = Url::parse(url).ok()?;
+                let mut path = parsed.path().to_string();
+                if let Some(query) = parsed.query() {
+                    path = path + "?" + query;
+                }
+                Some(format!("{}/{}", base, path.trim_start_matches('/')))
+            }
+        }
+    }
+}
+
+fn settings_path() -> PathBuf {
+    util::get_data_dir().join("settings.json")
+}
+
+/// Loads the settings, using the defaults when no settings file exists yet.
+pub fn load() -> Result<LauncherSettings, Error> {
+    let path = settings_path();
+    if !path.exists() {
+        return Ok(LauncherSettings::default());
+    }
+    let settings = serde_json::from_reader(File::open(path)?)?;
+    Ok(settings)
+}
+
+pub fn save(settings: &LauncherSettings) -> Result<(), Error> {
+    settings.validate()?;
+    util::write_atomic(&settings_path(), &serde_json::to_vec_pretty(settings)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mirror(url: &str, from: Option<&str>) -> Mirror {
+        Mirror {
+            url: url.to_string(),
+            from: from.map(|it| it.to_string()),
+        }
+    }
+
+    fn settings(mirrors: Vec<Mirror>) -> LauncherSettings {
+        LauncherSettings {
+            mirrors,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn rewrite_replaces_prefix() {
+        let mirror = mirror("https://mirror.example.com/jdk/", Some("https://download.java.net/java/"));
+        assert_eq!(
+            mirror.rewrite("https://download.java.net/java/17/openjdk.tar.gz"),
+            Some("https://mirror.example.com/jdk/17/openjdk.tar.gz".to_string())
+        );
+        assert_eq!(mirror.rewrite("https://github.com/foo/bar.zip"), None);
+    }
+
+    #[test]
+    fn rewrite_replaces_host_and_keeps_query() {
+        let mirror = mirror("https://mirror.example.com", None);
+        assert_eq!(
+            mirror.rewrite("https://github.com/foo/bar.zip?raw=true&v=2"),
+            Some("https://mirror.example.com/foo/bar.zip?raw=true&v=2".to_string())
+        );
+        assert_eq!(mirror.rewrite("not a url"), None);
+    }
+
+    #[test]
+    fn download_urls_tries_mirrors_first() {
+        let settings = settings(vec![
+            mirror("https://one.example.com", None),
+            mirror("https://two.example.com/", Some("https://github.com/")),
+        ]);
+        assert_eq!(
+            settings.download_urls("https://github.com/foo/bar.zip"),
+            vec![
+                "https://one.example.com/foo/bar.zip",
+                "https://two.example.com/foo/bar.zip",
+                "https://github.com/foo/bar.zip",
+            ]
+        );
+    }
+
+    #[test]
+    fn download_urls_skips_duplicates() {
+        let settings = settings(vec![
+            mirror("https://one.example.com", None),
+            mirror("https://one.example.com/", Some("https://github.com")),
+            mirror("https://github.com", None),
+        ]);
+        assert_eq!(
+            settings.download_urls("https://github.com/foo/bar.zip"),
+            vec!["https://one.example.com/foo/bar.zip", "https://github.com/foo/bar.zip"]
+        );
+    }
+
+    #[test]
+    fn download_urls_without_mirrors() {
+        assert_eq!(
+            settings(vec![]).download_urls("https://github.com/foo/bar.zip"),
+            vec!["https://github.com/foo/bar.zip"]
+        );
+    }
+}
@@ -0,0 +1,220 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::profiles::{self, Profile};
+use crate::util::{self, Error};
+
+/// Schema of `profiles.json` written by this version of the launcher.
+///
+/// - 0: a bare array of profiles.
+/// - 1: `{"schema": 1, "profiles": [...]}`, profiles have an `id`.
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct StoredProfilesRef<'a> {
+    schema: u32,
+    profiles: &'a [Profile],
+}
+
+#[derive(Deserialize)]
+struct StoredProfiles {
+    profiles: Vec<Profile>,
+}
+
+/// Path of the profiles file in a data directory.
+pub fn profiles_path(data_dir: &Path) -> PathBuf {
+    data_dir.join("profiles.json")
+}
+
+fn backup_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".bak");
+    path.with_file_name(name)
+}
+
+/// Loads the profiles, migrating older layouts and falling back to the backup when the file is
+/// damaged. Migrated profiles are written back right away.
+pub fn load() -> Result<Vec<Profile>, Error> {
+    load_from(&profiles_path(&util::get_data_dir()))
+}
+
+/// Loads the profiles from `path`, see [`load`].
+pub fn load_from(path: &Path) -> Result<Vec<Profile>, Error> {
+    let backup = backup_path(path);
+    if !path.exists() && !backup.exists() {
+        return Ok(vec![]);
+    }
+
+    let (mut profiles, migrated) = match read(path) {
+        Ok(loaded) => loaded,
+        // Never replace profiles of a newer launcher with an older backup
+        Err(e) if backup.exists() && !is_newer(path) => {
+            println!("Failed to read profiles, restoring backup: {:?}", e);
+            let (profiles, _) = read(&backup)?;
+            (profiles, true)
+        }
+        Err(e) => return Err(e),
+    };
+
+    let assigned = profiles::assign_ids(&mut profiles);
+    if migrated || assigned {
+        println!("Writing migrated profiles.");
+        save_to(path, &profiles)?;
+    }
+    Ok(profiles)
+}
+
+/// Reads and migrates a profiles file, returns whether it had to be migrated.
+fn read(path: &Path) -> Result<(Vec<Profile>, bool), Error> {
+    let value: Value = serde_json::from_slice(&std::fs::read(path)?)?;
+    let schema = schema_of(&value)?;
+    if schema > SCHEMA_VERSION {
+        return Err(Error::msg(&format!(
+            "Profiles were saved by a newer launcher (schema {}, supported up to {})",
+            schema, SCHEMA_VERSION
+        )));
+    }
+
+    let value = migrate(value, schema);
+    let stored: StoredProfiles = serde_json::from_value(value)?;
+    Ok((stored.profiles, schema != SCHEMA_VERSION))
+}
+
+/// Whether the file was written by a newer launcher, which this one must not overwrite.
+fn is_newer(path: &Path) -> bool {
+    std::fs::read(path)
+        .ok()
+        .and_then(|it| serde_json::from_slice::<Value>(&it).ok())
+        .and_then(|it| schema_of(&it).ok())
+        .map(|it| it > SCHEMA_VERSION)
+        .unwrap_or(false)
+}
+
+fn schema_of(value: &Value) -> Result<u32, Error> {
+    match value {
+        Value::Array(_) => Ok(0),
+        Value::Object(object) => object
+            .get("schema")
+            .and_then(Value::as_u64)
+            .map(|it| it as u32)
+            .ok_or_else(|| Error::msg("Profiles file has no schema version")),
+        _ => Err(Error::msg("Profiles file is neither a list nor an object")),
+    }
+}
+
+/// Upgrades a profiles file one schema at a time.
+fn migrate(mut value: Value, from: u32) -> Value {
+    for schema in from..SCHEMA_VERSION {
+        println!("Migrating profiles from schema {} to {}", schema, schema + 1);
+        value = match schema {
+            // Ids are assigned after loading, as they must be random.
+            0 => serde_json::json!({ "schema": 1, "profiles": value }),
+            _ => value,
+        };
+    }
+    value
+}
+
+/// Saves the profiles atomically, keeping the previous file as a backup if it was readable.
+pub fn save(profiles: &[Profile]) -> Result<(), Error> {
+    save_to(&profiles_path(&util::get_data_dir()), profiles)
+}
+
+/// Saves the profiles to `path`, see [`save`].
+pub fn save_to(path: &Path, profiles: &[Profile]) -> Result<(), Error> {
+    if is_newer(path) {
+        return Err(Error::msg("Profiles were saved by a newer launcher, refusing to overwrite them"));
+    }
+    if read(path).is_ok() {
+        std::fs::copy(path, backup_path(path))?;
+    }
+
+    let data = serde_json::to_vec_pretty(&StoredProfilesRef {
+        schema: SCHEMA_VERSION,
+        profiles,
+    })?;
+    util::write_atomic(path, &data)
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn profile(name: &str) -> Profile {
+        serde_json::from_value(serde_json::json!({
+            "id": Uuid::new_v4(),
+            "game": "bubble-blaster",
+            "name": name,
+            "version": "1.0.0",
+        }))
+        .unwrap()
+    }
+
+    fn stored(path: &Path) -> Value {
+        serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn migrates_a_bare_array() {
+        let path = util::test_dir("store-migrate").join("profiles.json");
+        let legacy = serde_json::json!([
+            { "game": "bubble-blaster", "name": "Main", "version": "1.0.0" },
+            { "game": "bubble-blaster", "name": "Modded", "version": "1.1.0" },
+        ]);
+        std::fs::write(&path, serde_json::to_vec(&legacy).unwrap()).unwrap();
+
+        let profiles = load_from(&path).unwrap();
+        assert_eq!(profiles.len(), 2);
+        assert!(profiles.iter().all(|it| !it.id.is_nil()));
+        assert_ne!(profiles[0].id, profiles[1].id);
+
+        // Written back, so the ids stay the same on the next load
+        let written = stored(&path);
+        assert_eq!(written["schema"], SCHEMA_VERSION);
+        let reloaded = load_from(&path).unwrap();
+        let ids: Vec<Uuid> = reloaded.iter().map(|it| it.id).collect();
+        assert_eq!(ids, profiles.iter().map(|it| it.id).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn restores_the_backup_of_a_corrupt_file() {
+        let path = util::test_dir("store-restore").join("profiles.json");
+        let main = profile("Main");
+        save_to(&path, &[main.clone()]).unwrap();
+        save_to(&path, &[main.clone(), profile("Other")]).unwrap();
+        std::fs::write(&path, b"{\"schema\": 1, \"profiles\": [").unwrap();
+
+        let profiles = load_from(&path).unwrap();
+        assert_eq!(profiles.len(), 1);
+        assert_eq!(profiles[0].id, main.id);
+        // The restored profiles replace the corrupt file
+        assert_eq!(stored(&path)["profiles"][0]["name"], "Main");
+    }
+
+    #[test]
+    fn refuses_to_overwrite_a_newer_schema() {
+        let path = util::test_dir("store-newer").join("profiles.json");
+        let newer = serde_json::json!({ "schema": SCHEMA_VERSION + 1, "profiles": [], "extra": true });
+        std::fs::write(&path, serde_json::to_vec(&newer).unwrap()).unwrap();
+        save_to(&path.with_file_name("profiles.json.bak"), &[profile("Old")]).unwrap();
+
+        assert!(load_from(&path).is_err());
+        assert!(save_to(&path, &[profile("Main")]).is_err());
+        assert_eq!(stored(&path), newer);
+    }
+
+    #[test]
+    fn save_keeps_the_previous_file_as_backup() {
+        let path = util::test_dir("store-backup").join("profiles.json");
+        save_to(&path, &[profile("First")]).unwrap();
+        assert!(!backup_path(&path).exists());
+
+        save_to(&path, &[profile("Second")]).unwrap();
+        assert_eq!(stored(&backup_path(&path))["profiles"][0]["name"], "First");
+        assert_eq!(stored(&path)["profiles"][0]["name"], "Second");
+    }
+}
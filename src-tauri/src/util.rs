@@ -1,11 +1,14 @@
 use std::env::consts::OS;
-use std::io;
-use std::path::PathBuf;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use semver::VersionReq;
 use tauri::InvokeError;
 
 use crate::game::{GameConfig, GameMetadata};
+use crate::package::PackageReport;
 
 #[cfg(target_os = "linux")]
 pub const PATH_SEPARATOR: &str = ":";
@@ -38,6 +41,18 @@ pub fn get_classpath(cfg: &GameConfig, meta: GameMetadata, data_dir: &String) ->
     cp
 }
 
+/// Replaces characters that aren't safe in file names on every platform.
+pub fn sanitize_file_name(name: &str) -> String {
+    let sanitized: String = name
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' || c == '.' { c } else { '_' })
+        .collect();
+    match sanitized.trim_matches('.') {
+        "" => "_".to_string(),
+        it => it.to_string(),
+    }
+}
+
 /// Runtime errors that can happen inside a Tauri application.
 #[derive(thiserror::Error, Debug)]
 #[non_exhaustive]
@@ -48,6 +63,25 @@ pub enum Error {
     /// Failed to download a file.
     #[error("Download error: {0}")]
     Download(String),
+    /// Downloaded file doesn't match its expected hash or size.
+    #[error("Checksum mismatch for {file}: expected {expected}, got {actual}")]
+    ChecksumMismatch {
+        file: String,
+        expected: String,
+        actual: String,
+    },
+    /// Archive entry that would be written outside the extraction directory.
+    #[error("Unsafe archive entry '{entry}': {reason}")]
+    UnsafeArchiveEntry {
+        entry: String,
+        reason: String,
+    },
+    /// Game package with problems, sent to the UI as the report so every problem can be listed.
+    #[error("Invalid game package: {0}")]
+    InvalidPackage(PackageReport),
+    /// Archive format that can't be extracted.
+    #[error("Unsupported archive format: {0}")]
+    UnsupportedArchive(String),
     /// Failed to launch a game version.
     #[error("Launch error: {0}")]
     Launch(String),
@@ -96,7 +130,10 @@ impl Error {
 
 impl From<Error> for InvokeError {
     fn from(value: Error) -> InvokeError {
-        InvokeError::from_anyhow(value.into_anyhow())
+        match value {
+            Error::InvalidPackage(report) => InvokeError::from(report),
+            _ => InvokeError::from_anyhow(value.into_anyhow()),
+        }
     }
 }
 
@@ -161,3 +198,44 @@ pub fn get_data_dir() -> PathBuf {
         }
     }.map(|it| it.join("UltreonGameLauncher")).unwrap()
 }
+
+/// Writes a file so it either has the old or the new contents, even if the launcher crashes.
+///
+/// The data goes to a temporary file next to `path` which is synced and then renamed over it.
+/// Milliseconds since the unix epoch, 0 when the clock is set before it.
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|it| it.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+pub fn write_atomic(path: &Path, data: &[u8]) -> Result<(), Error> {
+    let dir = path
+        .parent()
+        .ok_or_else(|| Error::msg(&format!("No parent directory: {}", path.to_string_lossy())))?;
+    std::fs::create_dir_all(dir)?;
+
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(".tmp");
+    let temp_path = dir.join(temp_name);
+    {
+        let mut file = File::create(&temp_path)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+    }
+    std::fs::rename(&temp_path, path)?;
+
+    // The rename itself is only durable once the directory is synced.
+    #[cfg(unix)]
+    File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+/// Fresh empty directory for a test.
+#[cfg(test)]
+pub fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("launcher-test-{}-{}", name, uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
@@ -3,7 +3,7 @@ import './App.css';
 import {invoke} from '@tauri-apps/api'
 import {listen} from '@tauri-apps/api/event'
 import {FaBars, FaX} from 'react-icons/fa6';
-import {load, Profile, PROFILES} from './Profiles';
+import {checkSdkOverride, errorMessage, load, Profile, PROFILES, saveLaunchSettings, SdkOverride} from './Profiles';
 import {toast} from 'react-toastify';
 import 'react-toastify/dist/ReactToastify.css';
 import ToastComponent from "./CustomToast.tsx";
@@ -18,10 +18,31 @@ document.oncontextmenu = e => {
 let onprogress = (_payload: DownloadInfo) => {
 };
 
+// eslint-disable-next-line @typescript-eslint/no-unused-vars
+let onopensettings = (_profile: Profile) => {
+};
+
 listen('downloadProgress', (progress) => {
   onprogress(progress.payload as DownloadInfo)
 });
 
+listen('gameExited', (event) => {
+  const exit = event.payload as GameExit
+  if (exit.kind === "normal" || exit.kind === "killed") return;
+  toast.error((
+    // @ts-ignore
+    <>
+      <b>{exit.kind === "outOfMemory" ? "Game ran out of memory!" : exit.kind === "startFailure" ? "Game failed to start!" : "Game crashed!"}</b>
+      <br/>Exit code: {exit.code ?? "unknown"}
+      {exit.crashReport ? <><br/>Crash report: {exit.crashReport}</> : null}
+    </>
+  ), {
+    position: toast.POSITION.TOP_RIGHT,
+    closeOnClick: true,
+    theme: "dark"
+  });
+});
+
 function MenuButton() {
   function ToggleMenu(): void {
     const elem = document.getElementById("SidePanel");
@@ -103,7 +124,7 @@ function PlayButton() {
 
     RevalidatePlayState(null);
     try {
-      await invoke("launch", {profile: PROF})
+      await invoke("launch", {id: PROF.id})
     } catch (e) {
       if (typeof(e) === "string") {
         toast.error((
@@ -136,11 +157,34 @@ function PlayButton() {
   );
 }
 
+function SettingsButton() {
+  function OpenSettings(event: MouseEvent<HTMLButtonElement>): void {
+    const elem = event.target as HTMLButtonElement;
+    if (elem.classList.contains('Disabled')) return;
+    if (selectedProfile == null) return;
+    onopensettings(selectedProfile)
+  }
+
+  return (
+    <div>
+      <button
+        id="SettingsButton"
+        className="Button Disabled"
+        onClick={OpenSettings}
+        type="button"
+      >
+        Settings
+      </button>
+    </div>
+  );
+}
+
 function BottomPanel() {
   return (
     <div>
       <div className="BottomPanel">
         <PlayButton/>
+        <SettingsButton/>
       </div>
     </div>
   );
@@ -152,20 +196,20 @@ function ProfileEntry(element: Profile): ReactElement {
   function SelectProfile(event: MouseEvent<HTMLButtonElement, globalThis.MouseEvent>) {
     const elem = event.currentTarget
     if (PROFILES.length === 0) load();
-    const game = PROFILES.find(value => value.name == elem.ariaLabel)
+    const game = PROFILES.find(value => value.id == elem.dataset.id)
     selectedProfile = game === undefined ? null : game;
 
     RevalidatePlayState(selectedProfile)
   }
 
   return (
-    <button className="ProfileEntry" aria-label={element.name} key={element.name} type="button" onClick={SelectProfile}>
+    <button className="ProfileEntry" aria-label={element.name} data-id={element.id} key={element.id} type="button" onClick={SelectProfile}>
       {name}
     </button>
   );
 }
 
-function Content(list: ReactElement<HTMLDivElement>, modal: ReactElement<HTMLDivElement>, progress: ReactElement<HTMLDivElement>) {
+function Content(list: ReactElement<HTMLDivElement>, modal: ReactElement<HTMLDivElement>, settings: ReactElement<HTMLDivElement>, progress: ReactElement<HTMLDivElement>) {
   return (
     <div>
       <div>
@@ -182,6 +226,9 @@ function Content(list: ReactElement<HTMLDivElement>, modal: ReactElement<HTMLDiv
       <div>
         {modal}
       </div>
+      <div>
+        {settings}
+      </div>
       {progress}
       {BottomPanel()}
       <ToastComponent/>
@@ -203,12 +250,23 @@ function hideModal() {
   inputElem.value = "";
 }
 
+class GameExit {
+  profile: string = "";
+  kind: "normal" | "crash" | "killed" | "outOfMemory" | "startFailure" = "normal";
+  code: number | null = null;
+  signal: number | null = null;
+  crashReport: string | null = null;
+  success: boolean = false;
+  killed: boolean = false;
+}
+
 class DownloadInfo {
   downloaded: number = 0;
   total: number = 0;
   percent: number = 0;
   downloading: boolean = false;
   status: string = "";
+  phase: "downloading" | "verifying" | "extracting" | "finalising" = "downloading";
 
   constructor() {
 
@@ -219,6 +277,10 @@ export default function App() {
   const [items, setItems] = useState<Profile[]>(PROFILES);
   const [newItem, setNewItem] = useState<Profile | null>(null);
   const [progress, setProgress] = useState<DownloadInfo>(new DownloadInfo());
+  const [settingsProfile, setSettingsProfile] = useState<Profile | null>(null);
+  const [overrideKind, setOverrideKind] = useState<string>("none");
+  const [overrideValue, setOverrideValue] = useState<string>("");
+  const [warnings, setWarnings] = useState<Array<string>>([]);
 
   useEffect(() => {
     const loadProfiles = async () => {
@@ -246,15 +308,15 @@ export default function App() {
 
   const LIST = (
     <div id="SidePanel">
-      {items.filter((item, pos, self) => self.findIndex(it => item.name == it.name) == pos).map((game) => ProfileEntry(game))}
+      {items.filter((item, pos, self) => self.findIndex(it => item.id == it.id) == pos).map((game) => ProfileEntry(game))}
     </div>
   )
 
   async function importProfile(name: string) {
     try {
       console.log("Attempting to import profile:" + name);
-      const profile: Profile = await invoke("import", {name: name}) as Profile
-      if (profile.game === 'error') {
+      const profile = await invoke("import", {name: name}) as Profile | null
+      if (profile === null) {
         console.log("Importing cancelled");
         return;
       }
@@ -268,6 +330,11 @@ export default function App() {
       hideModal();
     } catch (e) {
       console.error(e)
+      toast.error(errorMessage(e), {
+        position: toast.POSITION.TOP_RIGHT,
+        closeOnClick: true,
+        theme: "dark"
+      });
     }
   }
 
@@ -289,6 +356,85 @@ export default function App() {
     };
   }, []);
 
+  useEffect(() => {
+    onopensettings = (profile: Profile) => {
+      setSettingsProfile(profile);
+      setOverrideKind(profile.settings.sdkOverride?.kind ?? "none");
+      setOverrideValue(profile.settings.sdkOverride?.value ?? "");
+      setWarnings([]);
+    };
+  }, []);
+
+  function sdkOverride(): SdkOverride | undefined {
+    if (overrideKind === "none") return undefined;
+    return {kind: overrideKind, value: overrideValue.trim()} as SdkOverride;
+  }
+
+  async function checkSettings() {
+    const override = sdkOverride();
+    if (settingsProfile === null || override === undefined) {
+      setWarnings([]);
+      return;
+    }
+    try {
+      setWarnings(await checkSdkOverride(settingsProfile.id, override));
+    } catch (e) {
+      setWarnings([String(e)]);
+    }
+  }
+
+  async function saveSettings() {
+    if (settingsProfile === null) return;
+    try {
+      const saved = await saveLaunchSettings(settingsProfile.id, {...settingsProfile.settings, sdkOverride: sdkOverride()});
+      if (selectedProfile?.id === saved.profile.id) selectedProfile = saved.profile;
+      setSettingsProfile(null);
+      if (saved.warnings.length > 0) {
+        toast.warn((
+          // @ts-ignore
+          <>
+            <b>Saved with warnings:</b>
+            {saved.warnings.map(warning => <span key={warning}><br/>{warning}</span>)}
+          </>
+        ), {
+          position: toast.POSITION.TOP_RIGHT,
+          closeOnClick: true,
+          theme: "dark"
+        });
+      }
+    } catch (e) {
+      console.error(e)
+      setWarnings([String(e)]);
+    }
+  }
+
+  const SETTINGS = (
+    <div id="SettingsModalBG" className={settingsProfile !== null ? 'ModalBackground Shown' : 'ModalBackground'}>
+      <div id="SettingsModal" className='Modal'>
+        <b className='ModalTitle'>{settingsProfile?.name}: SDK override</b>
+        <select value={overrideKind} onChange={e => setOverrideKind(e.target.value)}>
+          <option value="none">None</option>
+          <option value="version">Version</option>
+          <option value="range">Version range</option>
+          <option value="path">Path</option>
+        </select>
+        {overrideKind !== "none" ? (
+          <input type='text' className='textInput' value={overrideValue} onChange={e => setOverrideValue(e.target.value)}/>
+        ) : null}
+        {warnings.length > 0 ? (
+          <ul className='Warnings'>
+            {warnings.map(warning => <li key={warning}>{warning}</li>)}
+          </ul>
+        ) : null}
+        <div className='ButtonGroup'>
+          <button type='button' onClick={() => setSettingsProfile(null)}>Cancel</button>
+          <button type='button' onClick={() => checkSettings()}>Check</button>
+          <button type='button' onClick={() => saveSettings()}>Save</button>
+        </div>
+      </div>
+    </div>
+  )
+
   const PROGRESS = (
     <div className={progress.downloading ? 'ProgressBar Shown' : 'ProgressBar'}>
       <div id="MainProgressBar" className='ProgressBarInner' style={{width: (progress.percent) + "%"}}/>
@@ -301,18 +447,21 @@ export default function App() {
   return (
     <>
       {TitleBar()}
-      {Content(LIST, MODAL, PROGRESS)}
+      {Content(LIST, MODAL, SETTINGS, PROGRESS)}
     </>
   );
 }
 
 function RevalidatePlayState(selectedProfile: Profile | null) {
   const elem = document.getElementById("PlayButton");
+  const settingsElem = document.getElementById("SettingsButton");
   if (selectedProfile == null) {
     elem?.classList.add("Disabled")
+    settingsElem?.classList.add("Disabled")
   } else {
     console.log("Selected Profile: " + selectedProfile.name);
     elem?.classList.remove("Disabled")
+    settingsElem?.classList.remove("Disabled")
   }
 }
 
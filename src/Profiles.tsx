@@ -1,9 +1,26 @@
 import { invoke } from "@tauri-apps/api";
 
+export type SdkOverride =
+  | {kind: "version", value: string}
+  | {kind: "range", value: string}
+  | {kind: "path", value: string};
+
+export class LaunchSettings {
+  jvmArgs: Array<string> = [];
+  minMemory?: number;
+  maxMemory?: number;
+  systemProperties: Record<string, string> = {};
+  gameArgs: Array<string> = [];
+  sdkOverride?: SdkOverride;
+  gameDirMode: "shared" | "isolated" = "shared";
+}
+
 export class Profile {
+  id!: string;
   game!: string;
   version!: string;
   name!: string;
+  settings: LaunchSettings = new LaunchSettings();
 }
 
 // eslint-disable-next-line react-refresh/only-export-components
@@ -19,4 +36,61 @@ export async function load() {
   }
 }
 
+function replaceProfile(profile: Profile) {
+  const index = PROFILES.findIndex(value => value.id === profile.id)
+  if (index >= 0) PROFILES[index] = profile
+}
+
+export class PackageProblem {
+  entry?: string;
+  message!: string;
+}
+
+/** Sent instead of a message when an imported game package is invalid. */
+export class PackageReport {
+  problems: Array<PackageProblem> = [];
+}
+
+export function errorMessage(error: unknown): string {
+  if (typeof error === "object" && error !== null && "problems" in error) {
+    return (error as PackageReport).problems
+      .map(it => it.entry ? `${it.entry}: ${it.message}` : it.message)
+      .join("\n")
+  }
+  return String(error)
+}
+
+export class SavedLaunchSettings {
+  profile!: Profile;
+  warnings: Array<string> = [];
+}
+
+export async function saveLaunchSettings(id: string, settings: LaunchSettings): Promise<SavedLaunchSettings> {
+  const saved = await invoke("set_launch_settings", {id: id, settings: settings}) as SavedLaunchSettings
+  replaceProfile(saved.profile)
+  return saved
+}
+
+export async function checkSdkOverride(id: string, sdkOverride: SdkOverride): Promise<Array<string>> {
+  return await invoke("check_sdk_override", {id: id, sdkOverride: sdkOverride}) as Array<string>
+}
+
+export async function updateProfile(profile: Profile): Promise<Profile> {
+  const updated = await invoke("update_profile", {profile: profile}) as Profile
+  replaceProfile(updated)
+  return updated
+}
+
+export async function deleteProfile(id: string) {
+  await invoke("delete_profile", {id: id})
+  PROFILES = PROFILES.filter(value => value.id !== id)
+}
+
+export async function duplicateProfile(id: string, name?: string): Promise<Profile> {
+  const profile = await invoke("duplicate_profile", {id: id, name: name}) as Profile
+  const index = PROFILES.findIndex(value => value.id === id)
+  PROFILES.splice(index + 1, 0, profile)
+  return profile
+}
+
 console.log(PROFILES)